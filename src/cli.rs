@@ -1,6 +1,7 @@
 use std::path::PathBuf;
 use structopt::StructOpt;
-use stderrlog;
+
+use super::config::LinkMode;
 
 #[derive(StructOpt, Debug)]
 #[structopt()]
@@ -26,8 +27,14 @@ pub enum Command {
     },
 
     MakeFulltext {
-        // Positional argument
-        url: String
+        /// Handling of links within the content: preserve, footnotes or remove
+        /// (overrides the configuration)
+        #[structopt(long = "links")]
+        links: Option<LinkMode>,
+
+        /// Feed urls, several feeds are merged into a single RSS feed
+        #[structopt(required = true)]
+        urls: Vec<String>,
     }
 }
 
@@ -46,5 +53,5 @@ pub fn init() -> CliOpt {
         .init()
         .unwrap();
 
-    return opt;
+    opt
 }
@@ -1,6 +1,11 @@
+use chrono::{DateTime, FixedOffset};
 use confique::Config;
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
 use std::path::Path;
+use std::time::Duration;
+
+use super::proxy::{ImageProxy, EXAMPLE_SECRET};
 
 #[derive(Config, Debug)]
 pub struct Conf {
@@ -14,7 +19,21 @@ pub struct Conf {
     pub extraction_limits: ExtractionLimits,
 
     #[config(nested)]
-    pub listen: ServerConf
+    pub listen: ServerConf,
+
+    #[config(nested)]
+    pub image_proxy: ImageProxyConf,
+
+    #[config(nested)]
+    pub merge: MergeConf,
+
+    /// Site specific settings, keyed by domain (also applying to subdomains)
+    #[config(default = {})]
+    pub domains: HashMap<String, DomainConf>,
+
+    /// Feed specific settings, keyed by feed url
+    #[config(default = {})]
+    pub feeds: HashMap<String, FeedConf>,
 }
 
 #[derive(Config, Debug)]
@@ -39,26 +58,419 @@ impl FullTextRSSFilterConf {
 }
 
 
-#[derive(Config, Debug, Clone, Copy)]
+#[derive(Config, Debug, Clone)]
 pub struct ExtractionOpts {
     pub max_items: Option<usize>,
+    pub max_age: Option<MaxAge>,
+    pub since: Option<Since>,
     #[config(default = true)]
     pub keep_failed: bool,
     #[config(default = false)]
     pub keep_original_content: bool,
+    pub placement: Option<ContentPlacement>,
+    #[config(default = "")]
+    pub separator: String,
+    #[config(default = false)]
+    pub resolve_redirects: bool,
+    #[config(default = false)]
+    pub strip_tracking_params: bool,
+    #[config(default = false)]
+    pub enrich_metadata: bool,
+    #[config(default = false)]
+    pub overwrite_metadata: bool,
+    #[config(default = false)]
+    pub lead_image: bool,
+    #[config(default = false)]
+    pub xss: bool,
+    #[config(default = false)]
+    pub allow_iframes: bool,
+    #[config(default = false)]
+    pub proxy_images: bool,
+    #[config(default = false)]
+    pub inline_images: bool,
+    #[config(default = 524288)]
+    pub inline_image_max_size: usize,
+    #[config(default = 5242880)]
+    pub inline_images_max_total: usize,
+    pub inline_image_max_width: Option<u32>,
+    #[config(default = "keep")]
+    pub unknown_iframes: IframePolicy,
+    #[config(default = "preserve")]
+    pub links: LinkMode,
+    pub template: Option<std::path::PathBuf>,
+    #[config(default = "content")]
+    pub rss_target: RssTarget,
+    #[config(default = false)]
+    pub reading_time: bool,
+    #[config(default = false)]
+    pub reading_time_header: bool,
+    #[config(default = 200)]
+    pub words_per_minute: usize,
+    #[config(default = "off")]
+    pub summarize: SummaryMode,
+    #[config(default = 3)]
+    pub summary_sentences: usize,
+    #[config(default = false)]
+    pub detect_language: bool,
+    #[config(default = [])]
+    pub lang: Vec<String>,
+    #[config(default = false)]
+    pub sort_by_date: bool,
+    #[config(default = false)]
+    pub dedup: bool,
+    #[config(default = 0)]
+    pub min_text_length: usize,
+    #[config(default = 0)]
+    pub min_paragraphs: usize,
+    #[config(default = [])]
+    pub fallbacks: Vec<ExtractionStage>,
+}
+
+/// The stages of extracting an article's content
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExtractionStage {
+    /// `ArticleScraper` using the site config (with its own readability fallback)
+    ///
+    /// Always runs first, so it is no valid fallback.
+    #[serde(skip_deserializing)]
+    SiteConfig,
+    /// Mozilla's readability run on the raw page
+    Readability,
+    /// The article body of the page's structured data (JSON-LD, microdata)
+    #[serde(alias = "json_ld")]
+    StructuredData,
+    /// The content provided by the feed itself
+    FeedContent,
+    /// The page's `og:description`
+    OgDescription,
+}
+
+/// Placement of the full-text relative to the original content of an item
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentPlacement {
+    /// Only keep the full-text
+    Replace,
+    /// Full-text after the original content
+    Append,
+    /// Full-text before the original content
+    Prepend,
+    /// Original content as summary (RSS description), full-text as content
+    Summary,
+    /// Original content in a collapsed `<details>` block, followed by the full-text
+    Details,
+}
+
+impl ContentPlacement {
+    /// The placement implied by the `keep_original_content` setting
+    pub fn from_keep_original(keep_original_content: bool) -> ContentPlacement {
+        if keep_original_content {
+            ContentPlacement::Append
+        } else {
+            ContentPlacement::Replace
+        }
+    }
+
+    /// The placement for a request, whose settings take precedence over the configured ones
+    pub fn effective(req_placement: Option<ContentPlacement>, req_keep_original: Option<bool>, conf_placement: Option<ContentPlacement>, conf_keep_original: bool) -> ContentPlacement {
+        req_placement
+            .or(req_keep_original.map(ContentPlacement::from_keep_original))
+            .or(conf_placement)
+            .unwrap_or(ContentPlacement::from_keep_original(conf_keep_original))
+    }
+}
+
+/// Handling of iframes which are no known embeds
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum IframePolicy {
+    /// Leave the iframe as is
+    Keep,
+    /// Replace the iframe by a link to its source
+    Convert,
+    /// Drop the iframe
+    Remove,
+}
+
+/// Handling of links within the extracted content
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkMode {
+    /// Keep links as they are
+    Preserve,
+    /// Replace links by numbered references to a list of links at the end
+    Footnotes,
+    /// Replace links by their text
+    Remove,
+}
+
+/// Method of summarizing the extracted full-text
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SummaryMode {
+    /// No summary
+    Off,
+    /// The leading sentences
+    Lead,
+    /// The most representative sentences by word frequency, in their original order
+    Ranked,
+}
+
+/// The elements of RSS items receiving the content
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RssTarget {
+    /// `content:encoded`, the description is left as is
+    Content,
+    /// `description`, dropping `content:encoded`
+    Description,
+    /// Both `content:encoded` and `description`
+    Both,
+    /// `content:encoded`, with the original content as description
+    Summary,
+}
+
+impl std::str::FromStr for LinkMode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<LinkMode, String> {
+        match mode {
+            "preserve" => Ok(LinkMode::Preserve),
+            "footnotes" => Ok(LinkMode::Footnotes),
+            "remove" => Ok(LinkMode::Remove),
+            _ => Err(format!("unknown link mode {:?}, expected preserve, footnotes or remove", mode)),
+        }
+    }
+}
+
+impl ExtractionStage {
+    /// Name of the stage, as used in the configuration
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExtractionStage::SiteConfig => "site_config",
+            ExtractionStage::Readability => "readability",
+            ExtractionStage::StructuredData => "structured_data",
+            ExtractionStage::FeedContent => "feed_content",
+            ExtractionStage::OgDescription => "og_description",
+        }
+    }
+
+    /// Whether the stage merely recovers the teaser of an article, which is
+    /// accepted regardless of the quality threshold
+    pub fn is_last_resort(&self) -> bool {
+        matches!(self, ExtractionStage::FeedContent | ExtractionStage::OgDescription)
+    }
 }
 
 #[derive(Config, Copy, Clone, Debug)]
 pub struct ExtractionLimits {
     pub max_items: Option<usize>,
+    /// Maximum number of feeds merged by a single request
+    #[config(default = 10)]
+    pub max_feeds: usize,
+    pub max_age: Option<MaxAge>,
+    /// Whether images may be inlined at all
+    #[config(default = true)]
+    pub inline_images: bool,
+    pub inline_images_max_total: Option<usize>,
+}
+
+/// Maximum age of items, in humantime format (e.g. `7d` or `12h 30m`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MaxAge(pub Duration);
+
+impl std::str::FromStr for MaxAge {
+    type Err = humantime::DurationError;
+
+    fn from_str(age: &str) -> Result<MaxAge, humantime::DurationError> {
+        humantime::parse_duration(age).map(MaxAge)
+    }
+}
+
+impl<'de> Deserialize<'de> for MaxAge {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<MaxAge, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Point in time items have to be published after, as RFC 3339, RFC 2822 or `YYYY-MM-DD` date
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Since(pub DateTime<FixedOffset>);
+
+impl std::str::FromStr for Since {
+    type Err = String;
+
+    fn from_str(date: &str) -> Result<Since, String> {
+        super::structured::parse_date(date)
+            .map(Since)
+            .ok_or_else(|| format!("invalid date {:?}, expected RFC 3339, RFC 2822 or YYYY-MM-DD", date))
+    }
+}
+
+impl<'de> Deserialize<'de> for Since {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Since, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
 }
 
-impl Into<super::feeds::ExtractionOpts> for ExtractionOpts {
-    fn into(self) -> super::feeds::ExtractionOpts {
+impl From<ExtractionOpts> for super::feeds::ExtractionOpts {
+    fn from(opts: ExtractionOpts) -> super::feeds::ExtractionOpts {
         super::feeds::ExtractionOpts {
-            max_items: self.max_items,
-            keep_failed: self.keep_failed,
-            keep_original_content: self.keep_original_content,
+            max_items: opts.max_items,
+            max_age: opts.max_age.map(|age| age.0),
+            since: opts.since.map(|since| since.0),
+            keep_failed: opts.keep_failed,
+            placement: opts.placement.unwrap_or(ContentPlacement::from_keep_original(opts.keep_original_content)),
+            separator: opts.separator,
+            resolve_redirects: opts.resolve_redirects,
+            strip_tracking_params: opts.strip_tracking_params,
+            enrich_metadata: opts.enrich_metadata,
+            overwrite_metadata: opts.overwrite_metadata,
+            lead_image: opts.lead_image,
+            xss: opts.xss,
+            allow_iframes: opts.allow_iframes,
+            quality: super::feeds::QualityThreshold {
+                min_text_length: opts.min_text_length,
+                min_paragraphs: opts.min_paragraphs,
+            },
+            fallbacks: opts.fallbacks,
+            domains: Default::default(),
+            image_proxy: None,
+            inline_images: opts.inline_images,
+            unknown_iframes: opts.unknown_iframes,
+            links: opts.links,
+            template: None,
+            rss_target: opts.rss_target,
+            reading_time: opts.reading_time,
+            reading_time_header: opts.reading_time_header,
+            words_per_minute: opts.words_per_minute,
+            summarize: opts.summarize,
+            summary_sentences: opts.summary_sentences,
+            detect_language: opts.detect_language,
+            languages: opts.lang,
+            filter: Default::default(),
+            source_filters: Default::default(),
+            sort_by_date: opts.sort_by_date,
+            dedup: opts.dedup,
+            inline_limits: super::images::InlineImageLimits {
+                max_size: opts.inline_image_max_size,
+                max_total: opts.inline_images_max_total,
+                max_width: opts.inline_image_max_width,
+            },
+        }
+    }
+}
+
+/// Settings of merging several feeds into one
+#[derive(Config, Debug, Clone)]
+pub struct MergeConf {
+    /// Title of the merged feed, defaults to the titles of the merged feeds
+    pub title: Option<String>,
+
+    /// Whether to tag items with their source feed as category
+    #[config(default = false)]
+    pub tag_source: bool,
+}
+
+#[derive(Config, Debug)]
+pub struct ImageProxyConf {
+    /// Key signing proxied image urls, the proxy is disabled without it
+    #[config(env = "IMAGE_PROXY_SECRET")]
+    pub secret: Option<String>,
+
+    /// Url the webserver is publicly reachable at, e.g. `https://example.org/fulltext/`
+    pub public_url: Option<String>,
+
+    /// Maximum size of a proxied image in bytes
+    #[config(default = 10485760)]
+    pub max_size: u64,
+
+    /// Accepted content types of proxied images, a trailing `*` matches any suffix
+    #[config(default = ["image/*"])]
+    pub content_types: Vec<String>,
+}
+
+impl ImageProxyConf {
+    /// The configured image proxy, if both secret and public url are set
+    pub fn build(&self) -> Result<Option<ImageProxy>, Box<dyn std::error::Error + Send + Sync>> {
+        let (Some(secret), Some(public_url)) = (&self.secret, &self.public_url) else {
+            return Ok(None);
+        };
+        if secret.trim() == EXAMPLE_SECRET {
+            return Err("image_proxy.secret is still set to the example value, choose a secret of your own".into());
+        }
+        let mut public_url = url::Url::parse(public_url)?;
+        // Make sure the proxy route is appended to, instead of replacing, the last path segment
+        if !public_url.path().ends_with('/') {
+            public_url.set_path(&format!("{}/", public_url.path()));
+        }
+        Ok(Some(ImageProxy::new(secret.clone(), public_url)))
+    }
+
+    /// Whether images of `content_type` may be proxied
+    pub fn accepts_content_type(&self, content_type: &str) -> bool {
+        let content_type = content_type.split(';').next().unwrap_or("").trim();
+        self.content_types.iter().any(|accepted| match accepted.strip_suffix('*') {
+            Some(prefix) => content_type.starts_with(prefix),
+            None => content_type.eq_ignore_ascii_case(accepted),
+        })
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct FeedConf {
+    /// Only keep items matching any of these patterns
+    #[serde(default)]
+    pub include: FilterConf,
+
+    /// Drop items matching any of these patterns
+    #[serde(default)]
+    pub exclude: FilterConf,
+}
+
+/// Regular expressions on the fields of an item
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct FilterConf {
+    pub title: Option<String>,
+    pub link: Option<String>,
+    pub author: Option<String>,
+    pub category: Option<String>,
+    /// Matched against the extracted text, or the original content if extraction fails
+    pub text: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct DomainConf {
+    /// Additional query parameters to strip from urls of this domain
+    #[serde(default)]
+    pub tracking_params: Vec<String>,
+
+    /// Alternate versions of an article to try, in order, when extraction
+    /// fails or yields too little text
+    #[serde(default)]
+    pub alternates: Vec<AlternateSource>,
+
+    /// Minimum length of the extracted text, overriding `min_text_length`
+    /// of the extraction settings
+    pub min_text_length: Option<usize>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AlternateSource {
+    /// The page referenced by `<link rel="amphtml">`
+    Amp,
+    /// The page referenced by `<link rel="canonical">`
+    Canonical,
+}
+
+impl AlternateSource {
+    /// Value of the `rel` attribute announcing this alternate
+    pub fn rel(&self) -> &'static str {
+        match self {
+            AlternateSource::Amp => "amphtml",
+            AlternateSource::Canonical => "canonical",
         }
     }
 }
@@ -73,9 +485,38 @@ pub struct ServerConf {
 }
 
 pub fn load_config(file: &std::path::Path) -> Result<Conf, confique::Error>  {
-    return Conf::builder()
+    Conf::builder()
         .env()
         .file(file)
         .load()
-        ;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::de::IntoDeserializer;
+    use serde::de::value::Error;
+
+    #[test]
+    fn accepts_json_ld_as_structured_data() {
+        let stage = ExtractionStage::deserialize(IntoDeserializer::<Error>::into_deserializer("json_ld")).unwrap();
+        assert_eq!(stage, ExtractionStage::StructuredData);
+    }
+
+    #[test]
+    fn rejects_site_config_as_fallback() {
+        assert!(ExtractionStage::deserialize(IntoDeserializer::<Error>::into_deserializer("site_config")).is_err());
+        assert!(ExtractionStage::deserialize(IntoDeserializer::<Error>::into_deserializer("readability")).is_ok());
+    }
+
+    #[test]
+    fn request_placement_takes_precedence() {
+        use ContentPlacement::*;
+        assert_eq!(ContentPlacement::effective(Some(Details), Some(false), Some(Append), true), Details);
+        assert_eq!(ContentPlacement::effective(None, Some(false), Some(Append), true), Replace);
+        assert_eq!(ContentPlacement::effective(None, Some(true), Some(Prepend), false), Append);
+        assert_eq!(ContentPlacement::effective(None, None, Some(Prepend), false), Prepend);
+        assert_eq!(ContentPlacement::effective(None, None, None, true), Append);
+        assert_eq!(ContentPlacement::effective(None, None, None, false), Replace);
+    }
 }
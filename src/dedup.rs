@@ -0,0 +1,132 @@
+use std::collections::HashSet;
+use url::Url;
+
+use super::urls;
+
+/// Texts whose simhashes differ in at most this many bits are considered duplicates
+const MAX_SIMHASH_DISTANCE: u32 = 3;
+
+/// Texts with fewer words are too short for a meaningful simhash
+const MIN_SIMHASH_WORDS: usize = 50;
+
+/// Number of consecutive words hashed together
+const SHINGLE_SIZE: usize = 3;
+
+/// The links and GUIDs of the items seen so far
+#[derive(Debug, Default)]
+pub struct Seen {
+    links: HashSet<String>,
+    guids: HashSet<String>,
+}
+
+impl Seen {
+    /// Remember an item by its `link` and `guid`
+    ///
+    /// Returns whether the item is new, i.e. neither its normalized link nor its GUID was seen before.
+    pub fn insert(&mut self, link: Option<&str>, guid: Option<&str>) -> bool {
+        let link = link.map(|link| match Url::parse(link) {
+            Ok(url) => urls::normalized_key(&url),
+            Err(_) => link.to_owned(),
+        });
+        let guid = guid.map(|guid| guid.trim().to_owned());
+        if link.as_ref().is_some_and(|link| self.links.contains(link))
+            || guid.as_ref().is_some_and(|guid| self.guids.contains(guid)) {
+            return false;
+        }
+        self.links.extend(link);
+        self.guids.extend(guid);
+        true
+    }
+}
+
+/// Similarity hash of `text`, `None` if the text is too short
+///
+/// Near identical texts yield hashes differing in few bits only.
+pub fn simhash(text: &str) -> Option<u64> {
+    let words = text.split_whitespace()
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<_>>();
+    if words.len() < MIN_SIMHASH_WORDS {
+        return None;
+    }
+    let mut weights = [0i64; 64];
+    for shingle in words.windows(SHINGLE_SIZE) {
+        let hash = fnv1a(shingle);
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            *weight += if hash & (1 << bit) != 0 { 1 } else { -1 };
+        }
+    }
+    Some(weights.iter().enumerate()
+        .filter(|(_, weight)| **weight > 0)
+        .fold(0, |hash, (bit, _)| hash | (1 << bit)))
+}
+
+/// Stable 64-bit FNV-1a hash of `words`
+fn fnv1a(words: &[String]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in words.join(" ").bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Drop the items whose text hash is near a preceding one, keeping their order
+pub fn drop_near_duplicates<T>(items: Vec<(T, Option<u64>)>) -> Vec<T> {
+    let mut kept_hashes = Vec::new();
+    items.into_iter()
+        .filter_map(|(item, hash)| {
+            if let Some(hash) = hash {
+                if kept_hashes.iter().any(|kept: &u64| (kept ^ hash).count_ones() <= MAX_SIMHASH_DISTANCE) {
+                    return None;
+                }
+                kept_hashes.push(hash);
+            }
+            Some(item)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(count: usize, offset: usize) -> String {
+        (offset..offset + count).map(|i| format!("word{}", i)).collect::<Vec<_>>().join(" ")
+    }
+
+    #[test]
+    fn skips_short_texts() {
+        assert_eq!(simhash(""), None);
+        assert_eq!(simhash(&words(MIN_SIMHASH_WORDS - 1, 0)), None);
+        assert!(simhash(&words(MIN_SIMHASH_WORDS, 0)).is_some());
+    }
+
+    #[test]
+    fn hashes_near_identical_texts_alike() {
+        let text = words(200, 0);
+        let edited = format!("{} trailing remark", text.to_uppercase());
+        let distance = (simhash(&text).unwrap() ^ simhash(&edited).unwrap()).count_ones();
+        assert!(distance <= MAX_SIMHASH_DISTANCE, "distance {}", distance);
+        let other = (simhash(&text).unwrap() ^ simhash(&words(200, 1000)).unwrap()).count_ones();
+        assert!(other > MAX_SIMHASH_DISTANCE, "distance {}", other);
+    }
+
+    #[test]
+    fn drops_near_duplicates_keeping_the_first() {
+        let hash = simhash(&words(200, 0));
+        let items = vec![("a", hash), ("b", None), ("c", hash), ("d", simhash(&words(200, 1000)))];
+        assert_eq!(drop_near_duplicates(items), vec!["a", "b", "d"]);
+    }
+
+    #[test]
+    fn remembers_links_and_guids() {
+        let mut seen = Seen::default();
+        assert!(seen.insert(Some("https://www.example.org/a/?utm_source=rss"), Some("1")));
+        assert!(!seen.insert(Some("http://example.org/a"), None));
+        assert!(!seen.insert(Some("https://example.org/b"), Some(" 1 ")));
+        assert!(seen.insert(Some("https://example.org/b"), Some("2")));
+        assert!(seen.insert(None, None));
+        assert!(seen.insert(None, None));
+    }
+}
@@ -0,0 +1,127 @@
+use lol_html::{element, rewrite_str, RewriteStrSettings};
+use lol_html::html_content::ContentType;
+use quick_xml::escape::escape;
+use url::Url;
+
+use super::config::IframePolicy;
+use super::html::get_attribute;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// An embedded player or post, as it is replaced in the content
+#[derive(Debug)]
+struct Embed {
+    /// The page of the embedded video or post
+    link: String,
+    thumbnail: Option<String>,
+    /// Text shown when the iframe lacks a title
+    label: &'static str,
+}
+
+fn youtube(url: &Url) -> Option<Embed> {
+    let id = url.path().strip_prefix("/embed/")?.trim_end_matches('/');
+    if id == "videoseries" {
+        let list = url.query_pairs().find(|(key, _)| key == "list")?.1;
+        return Some(Embed {
+            link: format!("https://www.youtube.com/playlist?list={}", list),
+            thumbnail: None,
+            label: "YouTube playlist",
+        });
+    }
+    (!id.is_empty()).then(|| Embed {
+        link: format!("https://www.youtube.com/watch?v={}", id),
+        thumbnail: Some(format!("https://img.youtube.com/vi/{}/hqdefault.jpg", id)),
+        label: "YouTube video",
+    })
+}
+
+fn vimeo(url: &Url) -> Option<Embed> {
+    let id = url.path().strip_prefix("/video/")?.trim_end_matches('/');
+    (!id.is_empty()).then(|| Embed {
+        link: format!("https://vimeo.com/{}", id),
+        thumbnail: None,
+        label: "Vimeo video",
+    })
+}
+
+fn dailymotion(url: &Url) -> Option<Embed> {
+    let id = url.path().strip_prefix("/embed/video/")?.trim_end_matches('/');
+    (!id.is_empty()).then(|| Embed {
+        link: format!("https://www.dailymotion.com/video/{}", id),
+        thumbnail: Some(format!("https://www.dailymotion.com/thumbnail/video/{}", id)),
+        label: "Dailymotion video",
+    })
+}
+
+fn twitter(url: &Url) -> Option<Embed> {
+    if !url.path().starts_with("/embed/") {
+        return None;
+    }
+    let id = url.query_pairs().find(|(key, _)| key == "id")?.1;
+    Some(Embed {
+        link: format!("https://twitter.com/i/web/status/{}", id),
+        thumbnail: None,
+        label: "Post on X (Twitter)",
+    })
+}
+
+/// Recognize the embed of a known video or social media site
+fn known_embed(url: &Url) -> Option<Embed> {
+    let host = url.host_str()?.trim_start_matches("www.").trim_start_matches("m.");
+    match host {
+        "youtube.com" | "youtube-nocookie.com" => youtube(url),
+        "player.vimeo.com" => vimeo(url),
+        "dailymotion.com" => dailymotion(url),
+        "platform.twitter.com" | "platform.x.com" => twitter(url),
+        _ => None,
+    }
+}
+
+/// Html linking to `link`, led by the `thumbnail` if there is one
+fn linked_preview(link: &str, thumbnail: Option<&str>, title: &str) -> String {
+    let link = escape(link);
+    let title = escape(title);
+    match thumbnail {
+        Some(thumbnail) => format!(
+            "<p><a href=\"{}\"><img src=\"{}\" alt=\"{}\"><br>{}</a></p>",
+            link, escape(thumbnail), title, title
+        ),
+        None => format!("<p><a href=\"{}\">{}</a></p>", link, title),
+    }
+}
+
+/// Replace the iframes of known embeds (YouTube, Vimeo, Dailymotion, Twitter) in `html`
+/// by linked previews, handling all other iframes according to `unknown`
+///
+/// Iframe sources are expected to be absolute.
+pub fn convert_embeds(html: &str, unknown: IframePolicy) -> Result<String> {
+    let settings = RewriteStrSettings::new()
+        .append_element_content_handler(element!("iframe", |el| {
+            let src = get_attribute(el, "src")
+                .or_else(|| get_attribute(el, "data-src"))
+                .and_then(|src| Url::parse(src.trim()).ok());
+            let title = get_attribute(el, "title")
+                .map(|title| title.trim().to_owned())
+                .filter(|title| !title.is_empty());
+
+            match src.as_ref().and_then(known_embed) {
+                Some(embed) => {
+                    let title = title.as_deref().unwrap_or(embed.label);
+                    el.replace(&linked_preview(&embed.link, embed.thumbnail.as_deref(), title), ContentType::Html);
+                },
+                None => match unknown {
+                    IframePolicy::Keep => (),
+                    IframePolicy::Remove => el.remove(),
+                    IframePolicy::Convert => match &src {
+                        Some(src) => {
+                            let title = title.as_deref().unwrap_or(src.as_str());
+                            el.replace(&linked_preview(src.as_str(), None, title), ContentType::Html);
+                        },
+                        None => el.remove(),
+                    },
+                },
+            }
+            Ok(())
+        }));
+    Ok(rewrite_str(html, settings)?)
+}
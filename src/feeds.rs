@@ -1,4 +1,4 @@
-use article_scraper::ArticleScraper;
+use article_scraper::{ArticleScraper, Readability};
 use reqwest::Client;
 use url::Url;
 use async_trait::async_trait;
@@ -14,12 +14,37 @@ use thiserror::Error;
 
 use std::cmp;
 
-use super::config::ExtractionLimits;
+use super::config::{DomainConf, ExtractionLimits, ExtractionStage, IframePolicy, MergeConf, LinkMode, ContentPlacement, RssTarget, SummaryMode};
+use super::dedup::{self, Seen};
+use super::embeds;
+use super::filters::{ItemFields, ItemFilter, Verdict};
+use super::html;
+use super::images::{self, InlineImageLimits};
+use super::language;
+use super::page::Page;
+use super::proxy::ImageProxy;
+use super::structured::{parse_date, StructuredArticle};
+use super::summary;
+use super::template::{ItemTemplate, TemplateItem};
+use super::urls;
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use chrono::{DateTime, FixedOffset};
 
 use std::panic::AssertUnwindSafe;
 use futures::FutureExt;
+use tokio::sync::OnceCell;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
 
-type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+const MEDIA_RSS_NAMESPACE: &str = "http://search.yahoo.com/mrss/";
+
+/// Namespace of the elements added by full-text-rs, e.g. `<ftr:wordCount>`
+const FTR_NAMESPACE_PREFIX: &str = "ftr";
+const FTR_NAMESPACE: &str = "https://crates.io/crates/full-text-rs";
 
 #[derive(Debug, Clone)]
 pub struct ExtractionOpts {
@@ -27,12 +52,166 @@ pub struct ExtractionOpts {
     /// None implies no restriction
     pub max_items: Option<usize>,
 
+    /// Skip items older than this, None implies no restriction
+    pub max_age: Option<std::time::Duration>,
+
+    /// Skip items published before this point in time
+    pub since: Option<DateTime<FixedOffset>>,
+
     /// Whether to keep items where extraction failes
     pub keep_failed: bool,
 
-    /// Whether to keep the original content,
-    /// so only append the full-text
-    pub keep_original_content: bool,
+    /// Placement of the full-text relative to the original content
+    pub placement: ContentPlacement,
+
+    /// Html put between the original content and the full-text
+    pub separator: String,
+
+    /// Whether to unwrap tracking wrappers and follow redirects before extraction,
+    /// replacing the item link with the resolved url
+    pub resolve_redirects: bool,
+
+    /// Whether to strip tracking query parameters from item links
+    /// and urls within the extracted content
+    pub strip_tracking_params: bool,
+
+    /// Whether to fill in missing item metadata (title, author, dates)
+    /// from the article page, which requires fetching it
+    pub enrich_metadata: bool,
+
+    /// Whether metadata from the article replaces the one provided by the feed
+    pub overwrite_metadata: bool,
+
+    /// Whether to attach the article's lead image to items lacking an enclosure
+    pub lead_image: bool,
+
+    /// Whether to sanitize the extracted content against XSS
+    pub xss: bool,
+
+    /// Whether sanitizing keeps iframes (e.g. embedded videos)
+    pub allow_iframes: bool,
+
+    /// Minimum quality of extracted content
+    pub quality: QualityThreshold,
+
+    /// Extraction stages to try, in order, when `ArticleScraper` fails
+    /// or its result does not meet the quality threshold
+    pub fallbacks: Vec<ExtractionStage>,
+
+    /// Site specific settings, keyed by domain
+    pub domains: Arc<HashMap<String, DomainConf>>,
+
+    /// Proxy to route images through, when set
+    pub image_proxy: Option<Arc<ImageProxy>>,
+
+    /// Whether to embed the article's images as data urls
+    pub inline_images: bool,
+
+    /// Caps on the images embedded into a single item
+    pub inline_limits: InlineImageLimits,
+
+    /// Handling of iframes other than known embeds (e.g. YouTube), which are always converted to links
+    pub unknown_iframes: IframePolicy,
+
+    /// Handling of links within the extracted content
+    pub links: LinkMode,
+
+    /// Template rendering the final content of every item, when set
+    pub template: Option<Arc<ItemTemplate>>,
+
+    /// The elements of RSS items receiving the content
+    pub rss_target: RssTarget,
+
+    /// Whether to annotate items with word count and reading time elements
+    pub reading_time: bool,
+
+    /// Whether to lead the full-text with a word count and reading time line
+    pub reading_time_header: bool,
+
+    /// Reading speed the reading time is estimated with
+    pub words_per_minute: usize,
+
+    /// Method of summarizing the full-text into the RSS description/Atom summary
+    pub summarize: SummaryMode,
+
+    /// Maximum number of sentences of summaries
+    pub summary_sentences: usize,
+
+    /// Whether to tag items (and the feed, if it lacks one) with the detected language
+    pub detect_language: bool,
+
+    /// Languages items have to be in to be kept, all if empty
+    pub languages: Vec<String>,
+
+    /// Rules keeping or dropping items by their fields and text
+    pub filter: ItemFilter,
+
+    /// Rules for the items of merged feeds, keyed by the url of their `<source>` feed,
+    /// taking precedence over `filter`
+    pub source_filters: Arc<HashMap<String, ItemFilter>>,
+
+    /// Whether to order items by date, newest first
+    pub sort_by_date: bool,
+
+    /// Whether to drop items repeating a preceding one's link, GUID or text
+    pub dedup: bool,
+}
+
+/// Length of text below which the alternates of a page (AMP, canonical) are tried,
+/// unless `min_text_length` is set
+const DEFAULT_MIN_TEXT_LENGTH: usize = 250;
+
+/// Thresholds extracted content has to meet to be accepted
+#[derive(Debug, Clone, Copy)]
+pub struct QualityThreshold {
+    /// Minimum number of characters of text
+    pub min_text_length: usize,
+    /// Minimum number of non-empty paragraphs
+    pub min_paragraphs: usize,
+}
+
+impl QualityThreshold {
+    pub fn accepts(&self, html: &str) -> bool {
+        (self.min_text_length == 0 || html::text_content(html).chars().count() >= self.min_text_length)
+            && (self.min_paragraphs == 0 || html::paragraph_count(html) >= self.min_paragraphs)
+    }
+
+    /// The threshold below which alternates of a page are tried, which defaults
+    /// to `DEFAULT_MIN_TEXT_LENGTH` characters
+    fn for_alternates(&self) -> QualityThreshold {
+        QualityThreshold {
+            min_text_length: if self.min_text_length == 0 { DEFAULT_MIN_TEXT_LENGTH } else { self.min_text_length },
+            ..*self
+        }
+    }
+}
+
+/// Length of an extracted article
+#[derive(Debug, Clone, Copy)]
+pub struct ArticleLength {
+    pub words: usize,
+    /// Estimated reading time in minutes, at least one
+    pub minutes: usize,
+}
+
+impl ArticleLength {
+    pub fn of(html: &str, words_per_minute: usize) -> ArticleLength {
+        let words = html::word_count(html);
+        ArticleLength {
+            words,
+            minutes: words.div_ceil(words_per_minute.max(1)).max(1),
+        }
+    }
+
+    /// Html line stating the length, leading the full-text
+    fn header(&self) -> String {
+        format!("<p><small>{} words · {} min read</small></p>", self.words, self.minutes)
+    }
+
+    /// The `ftr:wordCount` and `ftr:readingTime` elements, as name and value
+    fn elements(&self) -> [(&'static str, String); 2] {
+        [("wordCount", self.words.to_string()), ("readingTime", self.minutes.to_string())]
+    }
 }
 
 impl ExtractionOpts {
@@ -44,21 +223,297 @@ impl ExtractionOpts {
             (None, Some(param))        => Some(param),
             (None, None)               => None,
         };
+        bounded.max_age = match (self.max_age, limits.max_age) {
+            (Some(param), Some(limit)) => Some(cmp::min(param, limit.0)),
+            (Some(param), None)        => Some(param),
+            (None, Some(limit))        => Some(limit.0),
+            (None, None)               => None,
+        };
+        bounded.inline_images = self.inline_images && limits.inline_images;
+        if let Some(limit) = limits.inline_images_max_total {
+            bounded.inline_limits.max_total = cmp::min(self.inline_limits.max_total, limit);
+        }
         bounded
     }
+
+    /// Whether an item published or updated at `date` is recent enough to be processed
+    ///
+    /// Items without a date are always processed.
+    pub fn is_recent(&self, date: Option<DateTime<FixedOffset>>) -> bool {
+        let Some(date) = date else {
+            return true;
+        };
+        let max_age_cutoff = self.max_age
+            .and_then(|max_age| chrono::Duration::from_std(max_age).ok())
+            .and_then(|max_age| chrono::Utc::now().checked_sub_signed(max_age));
+        max_age_cutoff.is_none_or(|cutoff| date >= cutoff)
+            && self.since.is_none_or(|since| date >= since)
+    }
+
+    /// Domain specific settings for `url`, preferring the most specific domain
+    pub fn domain_conf(&self, url: &Url) -> Option<&DomainConf> {
+        let host = url.host_str()?;
+        self.domains.iter()
+            .filter(|(domain, _)| {
+                host == domain.as_str() || host.ends_with(&format!(".{}", domain))
+            })
+            .max_by_key(|(domain, _)| domain.len())
+            .map(|(_, conf)| conf)
+    }
+
+    /// The quality threshold for `url`, honouring domain specific settings
+    pub fn quality_for(&self, url: &Url) -> QualityThreshold {
+        let mut quality = self.quality;
+        if let Some(min_text_length) = self.domain_conf(url).and_then(|conf| conf.min_text_length) {
+            quality.min_text_length = min_text_length;
+        }
+        quality
+    }
+
+    /// The rules for `item`, by the feed it was merged from
+    fn item_filter(&self, item: &rss::Item) -> &ItemFilter {
+        item.source()
+            .and_then(|source| self.source_filters.get(source.url()))
+            .unwrap_or(&self.filter)
+    }
+
+    /// Language of `html`, detected only if items are tagged or filtered by language
+    fn detect_language(&self, html: &str) -> Option<&'static str> {
+        if self.detect_language || !self.languages.is_empty() {
+            language::detect(html)
+        } else {
+            None
+        }
+    }
+
+    /// Whether an item in `language` passes the language filter, items of unknown language always do
+    fn accepts_language(&self, language: Option<&str>) -> bool {
+        self.languages.is_empty() || language.is_none_or(|language| language::is_allowed(language, &self.languages))
+    }
+
+    /// Similarity hash of the text of `html`, if items are deduplicated
+    fn text_hash(&self, html: &str) -> Option<u64> {
+        if self.dedup {
+            dedup::simhash(&html::text_content(html))
+        } else {
+            None
+        }
+    }
+
+    /// Summary of the extracted `full_text` as escaped plain text, if requested
+    fn summarize(&self, full_text: &str) -> Option<String> {
+        summary::summarize(full_text, self.summarize, self.summary_sentences)
+            .map(|summary| quick_xml::escape::escape(&summary).into_owned())
+    }
+
+    /// Lead `full_text` with a line stating its `length`, if requested
+    fn annotate_length<'a>(&self, full_text: &'a str, length: &ArticleLength) -> Cow<'a, str> {
+        if self.reading_time_header {
+            Cow::Owned(length.header() + full_text)
+        } else {
+            Cow::Borrowed(full_text)
+        }
+    }
+
+    /// The `original` content of an item, sanitized along with the full-text if requested
+    fn original_content<'a>(&self, original: Option<&'a str>) -> Option<Cow<'a, str>> {
+        original.map(|original| if self.xss {
+            Cow::Owned(html::sanitize(original, self.allow_iframes))
+        } else {
+            Cow::Borrowed(original)
+        })
+    }
+
+    /// Sanitize the html fields `item` carries over from the source feed, if requested
+    fn sanitize_item(&self, item: &mut rss::Item) {
+        if self.xss {
+            item.description = item.description.take().map(|html| html::sanitize(&html, self.allow_iframes));
+            item.content = item.content.take().map(|html| html::sanitize(&html, self.allow_iframes));
+        }
+    }
+
+    /// Sanitize the html fields `entry` carries over from the source feed, if requested
+    fn sanitize_entry(&self, entry: &mut atom_syndication::Entry) {
+        if !self.xss {
+            return;
+        }
+        if let Some(content) = entry.content.as_mut().filter(|content| content.content_type() != Some("text")) {
+            content.value = content.value.take().map(|html| html::sanitize(&html, self.allow_iframes));
+        }
+        if let Some(summary) = entry.summary.as_mut().filter(|summary| summary.r#type != atom_syndication::TextType::Text) {
+            summary.value = html::sanitize(&summary.value, self.allow_iframes);
+        }
+    }
+
+    /// Combine the `original` content of an item with the extracted `full_text`
+    ///
+    /// Returns the new content of the item and, for `ContentPlacement::Summary`, its new summary.
+    pub fn place_content(&self, original: Option<&str>, full_text: &str) -> (String, Option<String>) {
+        let original = original.filter(|original| !original.trim().is_empty());
+        let Some(original) = original else {
+            return (full_text.to_owned(), None);
+        };
+        match self.placement {
+            ContentPlacement::Replace => (full_text.to_owned(), None),
+            ContentPlacement::Append => (format!("{}{}{}", original, self.separator, full_text), None),
+            ContentPlacement::Prepend => (format!("{}{}{}", full_text, self.separator, original), None),
+            ContentPlacement::Summary => (full_text.to_owned(), Some(original.to_owned())),
+            ContentPlacement::Details => (
+                format!("<details><summary>Original content</summary>{}</details>{}{}", original, self.separator, full_text),
+                None,
+            ),
+        }
+    }
+
+    /// Apply the configured url cleanups to `url_str`
+    ///
+    /// Unparsable urls are returned unchanged.
+    pub fn clean_url(&self, url_str: &str) -> String {
+        if !self.strip_tracking_params {
+            return url_str.to_owned();
+        }
+        match Url::parse(url_str) {
+            Ok(url) => {
+                let additional = self.domain_conf(&url)
+                    .map(|conf| conf.tracking_params.as_slice())
+                    .unwrap_or_default();
+                urls::strip_tracking_params(&url, additional).to_string()
+            },
+            Err(_) => url_str.to_owned(),
+        }
+    }
 }
 
 
 #[derive (Error, Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum FeedError {
     #[error("object is of no known feed")]
     NotAFeedTypeError,
-    #[error("item does not possess a link element")]
+    #[error("item does not possess a usable link")]
     NoUrlError,
     #[error("failed to retrieve article for url {0}")]
     NoArticleError(String),
 }
 
+/// Full-text content retrieved for a single item
+#[derive(Debug)]
+pub struct ExtractedArticle {
+    /// Final url of the article, after following redirects
+    pub url: Url,
+    pub html: String,
+    /// The stage which produced `html`
+    pub stage: ExtractionStage,
+    pub metadata: ArticleMetadata,
+    /// The article's lead image, only looked up when requested
+    pub lead_image: Option<Url>,
+}
+
+/// Metadata learned about an article while extracting it
+#[derive(Debug, Default, Clone)]
+pub struct ArticleMetadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub published: Option<DateTime<FixedOffset>>,
+    pub updated: Option<DateTime<FixedOffset>>,
+    /// Url of the article's image, possibly relative
+    pub image: Option<String>,
+}
+
+impl From<StructuredArticle> for ArticleMetadata {
+    fn from(structured: StructuredArticle) -> ArticleMetadata {
+        ArticleMetadata {
+            title: structured.headline,
+            author: structured.author,
+            published: structured.published,
+            updated: structured.modified,
+            image: structured.image,
+        }
+    }
+}
+
+impl ArticleMetadata {
+    /// Metadata from the Open Graph tags and the structured data of `page`,
+    /// preferring the latter
+    fn from_page(page: &Page) -> ArticleMetadata {
+        let open_graph = ArticleMetadata {
+            title: page.meta("og:title"),
+            // article:author is frequently a link to a profile page instead of a name
+            author: page.meta("author")
+                .or_else(|| page.meta("article:author").filter(|author| as_http_url(author).is_none())),
+            published: page.meta("article:published_time").as_deref().and_then(parse_date),
+            updated: page.meta("article:modified_time")
+                .or_else(|| page.meta("og:updated_time"))
+                .as_deref().and_then(parse_date),
+            image: page.meta("og:image"),
+        };
+        match page.structured_article() {
+            Some(structured) => ArticleMetadata::from(structured).or(open_graph),
+            None => open_graph,
+        }
+    }
+
+    /// Complete the fields missing in `self` with those of `other`
+    fn or(self, other: ArticleMetadata) -> ArticleMetadata {
+        ArticleMetadata {
+            title: self.title.or(other.title),
+            author: self.author.or(other.author),
+            published: self.published.or(other.published),
+            updated: self.updated.or(other.updated),
+            image: self.image.or(other.image),
+        }
+    }
+
+    /// Fill the fields `item` lacks, or all known fields when `overwrite` is set
+    fn fill_item(&self, item: &mut rss::Item, overwrite: bool) {
+        if let Some(title) = &self.title {
+            if overwrite || item.title().is_none_or(|title| title.trim().is_empty()) {
+                item.set_title(title.clone());
+            }
+        }
+        if let Some(author) = &self.author {
+            let has_creator = item.dublin_core_ext().is_some_and(|dc| !dc.creators().is_empty());
+            if overwrite || (item.author().is_none() && !has_creator) {
+                let mut dc = item.dublin_core_ext().cloned().unwrap_or_default();
+                dc.set_creators(vec![author.clone()]);
+                item.set_dublin_core_ext(dc);
+                // `<author>` holds an e-mail address, the name replaces it as `dc:creator`
+                item.set_author(None);
+            }
+        }
+        if let Some(date) = self.published.or(self.updated) {
+            if overwrite || item.pub_date().is_none() {
+                item.set_pub_date(date.to_rfc2822());
+            }
+        }
+    }
+
+    /// Fill the fields `entry` lacks, or all known fields when `overwrite` is set
+    fn fill_entry(&self, entry: &mut atom_syndication::Entry, overwrite: bool) {
+        if let Some(title) = &self.title {
+            if overwrite || entry.title().trim().is_empty() {
+                entry.set_title(title.as_str());
+            }
+        }
+        if let Some(author) = &self.author {
+            if overwrite || entry.authors().is_empty() {
+                entry.set_authors(vec![atom_syndication::Person { name: author.clone(), ..Default::default() }]);
+            }
+        }
+        if let Some(published) = self.published {
+            if overwrite || entry.published().is_none() {
+                entry.set_published(published);
+            }
+        }
+        // atom_syndication defaults a missing <updated> to the unix epoch
+        if let Some(updated) = self.updated.or(self.published) {
+            if overwrite || entry.updated().timestamp() == 0 {
+                entry.set_updated(updated);
+            }
+        }
+    }
+}
+
 #[async_trait]
 pub trait PatchableFeed : ToString {
     /// MIME Type to use for this feed
@@ -66,21 +521,45 @@ pub trait PatchableFeed : ToString {
 
     /// Path the given feed to include full-text content
     async fn patch_feed(&mut self, article_scraper: &ArticleScraper, client: &Client, extraction_opts: &ExtractionOpts);
+
+    /// Convert the feed to an RSS channel, e.g. for merging it with others
+    fn into_channel(self: Box<Self>) -> rss::Channel;
 }
 
 #[async_trait]
 impl PatchableFeed for rss::Channel {
     fn mime_type(&self) -> &'static str {
-        return "text/xml";
+        "text/xml"
     }
 
     /* fn write_to<W: std::io::Write>(&self, writer: W) -> Result<W> {
         self.write_to(writer)
     } */
 
+    fn into_channel(self: Box<Self>) -> rss::Channel {
+        *self
+    }
+
 
     async fn patch_feed(&mut self, article_scraper: &ArticleScraper, client: &Client, extraction_opts: &ExtractionOpts) {
-        let items = self.items();
+        let feed_language = self.language();
+        // Filter by metadata (including the declared language) before extracting, saving the fetches of dropped items
+        let mut items = self.items().iter()
+            .filter(|item| extraction_opts.is_recent(item_date(item)))
+            .filter(|item| extraction_opts.accepts_language(item_language(item).or(feed_language)))
+            .map(|item| {
+                let filter = extraction_opts.item_filter(item);
+                (item, filter, filter.check_metadata(&item_fields(item)))
+            })
+            .filter(|(_, _, verdict)| *verdict != Verdict::Drop)
+            .collect::<Vec<_>>();
+        if extraction_opts.sort_by_date {
+            items.sort_by_cached_key(|(item, _, _)| cmp::Reverse(item_date(item)));
+        }
+        if extraction_opts.dedup {
+            let mut seen = Seen::default();
+            items.retain(|(item, _, _)| seen.insert(get_item_link(item).as_deref(), item.guid().map(|guid| guid.value())));
+        }
 
         // Handle max_items
         let len = if let Some(max_items) = extraction_opts.max_items {
@@ -89,34 +568,72 @@ impl PatchableFeed for rss::Channel {
             items.len()
         };
 
-        let new_items = futures::future::join_all(items[..len].iter().map(|item| async move {
+        let new_items = futures::future::join_all(items[..len].iter().map(|&(item, filter, verdict)| async move {
+            let mut new_item = item.clone();
+            extraction_opts.sanitize_item(&mut new_item);
+            new_item.set_link(item.link().map(|link| extraction_opts.clean_url(link)));
+
             // Get fulltext
-            match item_to_article(article_scraper, &client, &item).await {
-                Ok(str) => {
-                    let body = if extraction_opts.keep_original_content {
-                        (if let Some(content) = item.content() {
-                            content.to_string()
-                        } else {
-                            item.description().map(|text| {text.to_owned()}).unwrap_or("".to_string())
-                        }) + &str
-                    } else {
-                        str
-                    };
-
-                    let mut new_item = item.clone();
+            match item_to_article(article_scraper, client, &new_item, extraction_opts).await {
+                Ok(article) => {
+                    if !filter.accepts_html(verdict, &article.html) {
+                        debug!("Dropping item {:?}, filtered by its text", item.link());
+                        return None;
+                    }
+                    let original = extraction_opts.original_content(item_original_content(item));
+                    let length = ArticleLength::of(&article.html, extraction_opts.words_per_minute);
+                    let full_text = extraction_opts.annotate_length(&article.html, &length);
+                    let (body, summary) = extraction_opts.place_content(original.as_deref(), &full_text);
+                    // The original content as summary takes precedence over a generated one
+                    let summary = summary.or_else(|| extraction_opts.summarize(&article.html));
+
                     new_item.set_content(Some(body));
-                    Some(new_item)
+                    if extraction_opts.reading_time {
+                        attach_item_length(&mut new_item, &length);
+                    }
+                    article.metadata.fill_item(&mut new_item, extraction_opts.overwrite_metadata);
+                    if let Some(image) = &article.lead_image {
+                        attach_item_image(&mut new_item, image);
+                    }
+                    if extraction_opts.resolve_redirects {
+                        new_item.set_link(Some(article.url.to_string()));
+                    }
+                    if let Some(template) = &extraction_opts.template {
+                        render_item(template, &mut new_item, original.as_deref(), Some((&article, length)));
+                    }
+                    write_rss_target(&mut new_item, extraction_opts.rss_target, summary, original.as_deref());
+                    tag_item_language(&mut new_item, &article.html, feed_language, extraction_opts)
+                        .then(|| (new_item, extraction_opts.text_hash(&article.html)))
                 }
                 Err(_e) => {
-                    if extraction_opts.keep_failed {
-                        Some(item.clone())
-                    } else {
-                        None
+                    let original = item_original_content(item).unwrap_or_default();
+                    if !extraction_opts.keep_failed || !filter.accepts_html(verdict, original) {
+                        return None;
+                    }
+                    let sanitized = extraction_opts.original_content(Some(original));
+                    if let Some(template) = &extraction_opts.template {
+                        render_item(template, &mut new_item, sanitized.as_deref(), None);
                     }
+                    write_rss_target(&mut new_item, extraction_opts.rss_target, None, sanitized.as_deref());
+                    tag_item_language(&mut new_item, original, feed_language, extraction_opts).then_some((new_item, None))
                 },
             }
-        })).await.into_iter().filter_map(|x| x).collect::<Vec<_>>();
+        })).await.into_iter().flatten().collect::<Vec<_>>();
+        // Duplicates by text are only known after extraction
+        let new_items = dedup::drop_near_duplicates(new_items);
 
+        if extraction_opts.detect_language && self.language().is_none() {
+            let languages = new_items.iter()
+                .filter_map(|item| item.dublin_core_ext())
+                .flat_map(|dc| dc.languages().iter().map(|language| language.as_str()));
+            self.set_language(language::most_common(languages));
+        }
+        if new_items.iter().any(|item| item.extensions().contains_key("media")) {
+            self.namespaces.entry("media".to_owned()).or_insert_with(|| MEDIA_RSS_NAMESPACE.to_owned());
+        }
+        if new_items.iter().any(|item| item.extensions().contains_key(FTR_NAMESPACE_PREFIX)) {
+            self.namespaces.entry(FTR_NAMESPACE_PREFIX.to_owned()).or_insert_with(|| FTR_NAMESPACE.to_owned());
+        }
         self.set_items(new_items);
     }
 }
@@ -124,11 +641,37 @@ impl PatchableFeed for rss::Channel {
 #[async_trait]
 impl PatchableFeed for atom_syndication::Feed {
     fn mime_type(&self) -> &'static str {
-        return "application/atom+xml";
+        "application/atom+xml"
+    }
+
+    fn into_channel(self: Box<Self>) -> rss::Channel {
+        let mut channel = rss::Channel::default();
+        channel.set_title(self.title().as_str());
+        if let Some(link) = self.links().iter().find(|link| link.rel() == "alternate") {
+            channel.set_link(link.href());
+        }
+        channel.set_description(self.subtitle().map(|subtitle| subtitle.as_str()).unwrap_or_default());
+        channel.set_language(self.lang().map(|lang| lang.to_owned()));
+        channel.set_items(self.entries().iter().map(entry_to_item).collect::<Vec<_>>());
+        channel
     }
 
     async fn patch_feed(&mut self, article_scraper: &ArticleScraper, client: &Client, extraction_opts: &ExtractionOpts) {
-        let items = self.entries();
+        let feed_language = self.lang();
+        // Filter by metadata (including the declared language) before extracting, saving the fetches of dropped entries
+        let mut items = self.entries().iter()
+            .filter(|entry| extraction_opts.is_recent(entry_date(entry)))
+            .filter(|entry| extraction_opts.accepts_language(entry_language(entry).or(feed_language)))
+            .map(|entry| (entry, extraction_opts.filter.check_metadata(&entry_fields(entry))))
+            .filter(|(_, verdict)| *verdict != Verdict::Drop)
+            .collect::<Vec<_>>();
+        if extraction_opts.sort_by_date {
+            items.sort_by_cached_key(|(entry, _)| cmp::Reverse(entry_date(entry)));
+        }
+        if extraction_opts.dedup {
+            let mut seen = Seen::default();
+            items.retain(|(entry, _)| seen.insert(get_primary_link(entry).as_deref(), Some(entry.id())));
+        }
 
         // Handle max_items
         let len = if let Some(max_items) = extraction_opts.max_items {
@@ -137,38 +680,75 @@ impl PatchableFeed for atom_syndication::Feed {
             items.len()
         };
 
-        let new_items = futures::future::join_all(items[..len].iter().map(|item| async move {
+        let new_items = futures::future::join_all(items[..len].iter().map(|&(item, verdict)| async move {
+            let mut new_item = item.clone();
+            extraction_opts.sanitize_entry(&mut new_item);
+            for link in new_item.links.iter_mut() {
+                let cleaned = extraction_opts.clean_url(link.href());
+                link.set_href(cleaned);
+            }
+
             // Get fulltext
-            match entry_to_article(article_scraper, &client, &item).await {
-                Ok(str) => {
-                    let body = if extraction_opts.keep_original_content {
-                        (if let Some(content) = item.content() {
-                            content.value().unwrap_or("").to_string()
-                        } else {
-                            item.summary().map(|text| {text.value.to_owned()}).unwrap_or("".to_string())
-                        }) + &str
-                    } else {
-                        str
-                    };
-
-                    let mut new_item = item.clone();
-                    new_item.set_summary(None);
+            match entry_to_article(article_scraper, client, &new_item, extraction_opts).await {
+                Ok(article) => {
+                    if !extraction_opts.filter.accepts_html(verdict, &article.html) {
+                        debug!("Dropping entry {:?}, filtered by its text", item.id());
+                        return None;
+                    }
+                    let original = extraction_opts.original_content(entry_original_content(item));
+                    let length = ArticleLength::of(&article.html, extraction_opts.words_per_minute);
+                    let full_text = extraction_opts.annotate_length(&article.html, &length);
+                    let (body, summary) = extraction_opts.place_content(original.as_deref(), &full_text);
+                    // The original content as summary takes precedence over a generated one
+                    let summary = summary.or_else(|| extraction_opts.summarize(&article.html));
+
+                    if extraction_opts.resolve_redirects {
+                        set_alternate_link(&mut new_item, article.url.as_str());
+                    }
+                    article.metadata.fill_entry(&mut new_item, extraction_opts.overwrite_metadata);
+                    if let Some(image) = &article.lead_image {
+                        attach_entry_image(&mut new_item, image);
+                    }
+                    if extraction_opts.reading_time {
+                        attach_entry_length(&mut new_item, &length);
+                    }
+                    new_item.set_summary(summary.map(atom_syndication::Text::html));
                     let mut content = atom_syndication::Content::default();
                     content.set_value(Some(body));
                     content.set_content_type(Some("html".to_string()));
+                    // Keep the declared language, the fallback of detection
+                    content.lang = entry_language(item).map(str::to_owned);
                     new_item.set_content(Some(content));
-                    Some(new_item)
+                    if let Some(template) = &extraction_opts.template {
+                        render_entry(template, &mut new_item, original.as_deref(), Some((&article, length)));
+                    }
+                    tag_entry_language(&mut new_item, &article.html, feed_language, extraction_opts)
+                        .then(|| (new_item, extraction_opts.text_hash(&article.html)))
                 }
                 Err(_e) => {
-                    if extraction_opts.keep_failed {
-                        Some(item.clone())
-                    } else {
-                        None
+                    let original = entry_original_content(item).unwrap_or_default();
+                    if !extraction_opts.keep_failed || !extraction_opts.filter.accepts_html(verdict, original) {
+                        return None;
                     }
+                    if let Some(template) = &extraction_opts.template {
+                        render_entry(template, &mut new_item, extraction_opts.original_content(entry_original_content(item)).as_deref(), None);
+                    }
+                    tag_entry_language(&mut new_item, original, feed_language, extraction_opts).then_some((new_item, None))
                 },
             }
-        })).await.into_iter().filter_map(|x| x).collect::<Vec<_>>();
+        })).await.into_iter().flatten().collect::<Vec<_>>();
+        // Duplicates by text are only known after extraction
+        let new_items = dedup::drop_near_duplicates(new_items);
 
+        if extraction_opts.detect_language && self.lang().is_none() {
+            let languages = new_items.iter()
+                .filter_map(|entry| entry.content().and_then(|content| content.lang.as_deref()));
+            let language = language::most_common(languages);
+            self.set_lang(language);
+        }
+        if new_items.iter().any(|entry| entry.extensions().contains_key(FTR_NAMESPACE_PREFIX)) {
+            self.namespaces.entry(FTR_NAMESPACE_PREFIX.to_owned()).or_insert_with(|| FTR_NAMESPACE.to_owned());
+        }
         self.set_entries(new_items);
     }
 }
@@ -214,11 +794,67 @@ pub fn determine_feed_type(content: &[u8]) -> Result<FeedType> {
 pub async fn get_fulltext_feed(scraper: &ArticleScraper, feed_url: &str, extraction_opts: &ExtractionOpts) -> Result<Box<dyn PatchableFeed + Send>> {
     let client = Client::new();
     let mut patchable = get_feed(&client, feed_url).await?;
-    patchable.patch_feed(&scraper, &client, &extraction_opts).await;
+    patchable.patch_feed(scraper, &client, extraction_opts).await;
 
     Ok(patchable)
 }
 
+/// Fetch the feeds at `feed_urls` and merge their items into a single full-text RSS feed
+///
+/// The items are sorted by date and deduplicated. Feeds failing to load are skipped, unless all do.
+/// Items are attributed to their feed as `<source>`, the `feed_filters` configured for it apply
+/// to them, field by field after the filter of `extraction_opts`.
+pub async fn get_merged_fulltext_feed(scraper: &ArticleScraper, feed_urls: &[String], merge_conf: &MergeConf, feed_filters: &HashMap<String, ItemFilter>, extraction_opts: &ExtractionOpts) -> Result<Box<dyn PatchableFeed + Send>> {
+    let client = Client::new();
+    let sources = futures::future::join_all(feed_urls.iter().map(|url| get_feed(&client, url))).await;
+
+    let mut merged = rss::Channel::default();
+    let mut titles = Vec::new();
+    let mut source_filters = HashMap::new();
+    let mut last_error = None;
+    for (url, source) in feed_urls.iter().zip(sources) {
+        let mut source = match source {
+            Ok(source) => source.into_channel(),
+            Err(e) => {
+                warn!("Failed to fetch feed {} for merging: {:?}", url, e);
+                last_error = Some(e);
+                continue;
+            },
+        };
+        let title = Some(source.title().trim()).filter(|title| !title.is_empty()).unwrap_or(url).to_owned();
+        for item in source.items.iter_mut() {
+            item.set_source(rss::Source { url: url.clone(), title: Some(title.clone()) });
+            if merge_conf.tag_source {
+                item.categories.push(rss::Category { name: title.clone(), domain: Some(url.clone()) });
+            }
+        }
+        if let Some(filter) = feed_filters.get(url) {
+            source_filters.insert(url.clone(), extraction_opts.filter.clone().or(Some(filter)));
+        }
+        if merged.link().is_empty() {
+            merged.set_link(source.link());
+        }
+        for (prefix, namespace) in source.namespaces {
+            merged.namespaces.entry(prefix).or_insert(namespace);
+        }
+        merged.items.append(&mut source.items);
+        titles.push(title);
+    }
+    if titles.is_empty() {
+        return Err(last_error.unwrap_or_else(|| "no feeds to merge".into()));
+    }
+    merged.set_title(merge_conf.title.clone().unwrap_or_else(|| titles.join(" + ")));
+    merged.set_description(format!("Merged from {}", titles.join(", ")));
+
+    let mut extraction_opts = extraction_opts.clone();
+    extraction_opts.sort_by_date = true;
+    extraction_opts.dedup = true;
+    extraction_opts.source_filters = Arc::new(source_filters);
+    merged.patch_feed(scraper, &client, &extraction_opts).await;
+
+    Ok(Box::new(merged))
+}
+
 /// Fetch `url` and transform it to a parsed, patchable Feed
 async fn get_feed(client: &Client, url: &str) -> Result<Box<dyn PatchableFeed + Send>> {
     debug!("Fetching: {}", url);
@@ -231,44 +867,704 @@ async fn get_feed(client: &Client, url: &str) -> Result<Box<dyn PatchableFeed +
     let feedtype = determine_feed_type(&content);
     debug!("Determined FeedType: {:?}", feedtype);
 
-    return match feedtype? {
+    match feedtype? {
         FeedType::RssFeed  => Ok(Box::new(rss::Channel::read_from(&content[..])?)),
         FeedType::AtomFeed => Ok(Box::new(atom_syndication::Feed::read_from(&content[..])?)),
-
-    };
+    }
 }
 
 /// Helper converting an url to full-text content
-async fn url_to_article(scraper: &ArticleScraper, client: &Client, url_str: &str) -> Result<String> {
+async fn url_to_article(scraper: &ArticleScraper, client: &Client, url_str: &str, original_content: Option<&str>, extraction_opts: &ExtractionOpts) -> Result<ExtractedArticle> {
     debug!("Retrieving fulltext for {}", url_str);
-    let url = Url::parse(&url_str)?;
-    let article_result = AssertUnwindSafe(scraper.parse(&url, false, &client, None)).catch_unwind().await;
+    let mut url = Url::parse(url_str)?;
+    if extraction_opts.resolve_redirects {
+        url = urls::resolve_url(client, &url).await;
+    }
+    if extraction_opts.strip_tracking_params {
+        url = Url::parse(&extraction_opts.clean_url(url.as_str()))?;
+    }
+    let page = OnceCell::new();
+
+    let Some((html, stage, mut metadata, scraped_url)) = extract_content(scraper, client, &url, &page, original_content, extraction_opts).await else {
+        return Err(FeedError::NoArticleError(url_str.to_string()).into());
+    };
+    if extraction_opts.enrich_metadata {
+        if let Some(page) = get_page(&page, client, &url).await {
+            metadata = metadata.or(ArticleMetadata::from_page(page));
+        }
+    }
+    let lead_image = if extraction_opts.lead_image {
+        find_lead_image(client, &url, scraped_url.as_ref().unwrap_or(&url), &page, &html, &metadata).await
+    } else {
+        None
+    };
+    // Without enrichment, only the metadata of the structured data stage is used
+    if !extraction_opts.enrich_metadata && stage != ExtractionStage::StructuredData {
+        metadata = ArticleMetadata::default();
+    }
+    // Relative urls are relative to the page the content was taken from, after redirects
+    let base = match (&scraped_url, page.get()) {
+        (Some(scraped_url), _) => scraped_url.clone(),
+        (None, Some(Some(page))) => page.base_url(),
+        (None, _) => url.clone(),
+    };
+    // An alternate is only scraped once the article page has been fetched
+    let url = match page.get() {
+        Some(Some(page)) => page.url.clone(),
+        _ => scraped_url.unwrap_or(url),
+    };
+    Ok(postprocess_article(client, ExtractedArticle { url, html, stage, metadata, lead_image }, &base, extraction_opts).await)
+}
+
+/// Run the extraction stages on `url`, returning the first result passing the quality threshold
+///
+/// Along with the content, returns the final url of the page `ArticleScraper` extracted it from,
+/// the article's or an alternate's.
+async fn extract_content(scraper: &ArticleScraper, client: &Client, url: &Url, page: &OnceCell<Option<Page>>, original_content: Option<&str>, extraction_opts: &ExtractionOpts) -> Option<(String, ExtractionStage, ArticleMetadata, Option<Url>)> {
+    let quality = extraction_opts.quality_for(url);
+
+    // Stage 1: ArticleScraper, possibly on an alternate version of the page
+    let scraped = scrape(scraper, client, url).await
+        .inspect_err(|e| debug!("Failed to extract {}: {:?}", url, e))
+        .ok();
+    // Short articles are worth trying an alternate for, even if they pass the threshold
+    let alternate_quality = quality.for_alternates();
+    if !scraped.as_ref().is_some_and(|(html, _, _)| alternate_quality.accepts(html)) {
+        if let Some((html, metadata, alternate)) = scrape_alternates(scraper, client, url, page, extraction_opts, &alternate_quality).await {
+            return Some((html, ExtractionStage::SiteConfig, metadata, Some(alternate)));
+        }
+    }
+    if let Some((html, metadata, scraped_url)) = scraped.filter(|(html, _, _)| quality.accepts(html)) {
+        return Some((html, ExtractionStage::SiteConfig, metadata, Some(scraped_url)));
+    }
+
+    // Further stages, as configured
+    for stage in &extraction_opts.fallbacks {
+        let mut metadata = ArticleMetadata::default();
+        let candidate = match stage {
+            // Not accepted as a fallback, it already ran above
+            ExtractionStage::SiteConfig => None,
+            ExtractionStage::Readability => match get_page(page, client, url).await {
+                Some(page) => readability(page).await,
+                None => None,
+            },
+            ExtractionStage::StructuredData => match get_page(page, client, url).await.and_then(|page| page.structured_article()) {
+                Some(structured) => {
+                    let html = structured_body(&structured);
+                    metadata = structured.into();
+                    html
+                },
+                None => None,
+            },
+            ExtractionStage::FeedContent => original_content.map(|content| content.to_owned()),
+            ExtractionStage::OgDescription => get_page(page, client, url).await
+                .and_then(|page| page.meta("og:description"))
+                .map(|description| html::text_to_html(&description)),
+        };
+        match candidate {
+            // The last resorts are taken as they are
+            Some(html) if stage.is_last_resort() || quality.accepts(&html) => return Some((html, *stage, metadata, None)),
+            Some(_) => debug!("Fallback {:?} for {} is below the quality threshold", stage, url),
+            None => debug!("Fallback {:?} for {} yields no content", stage, url),
+        }
+    }
+    None
+}
+
+/// Determine the lead image of an article
+///
+/// Fallback chain: the image found along with the content (site config thumbnail or structured data),
+/// the first large image in the extracted html, the page's `og:image`.
+/// Images found along with the content are relative to `source`, the page it was extracted from.
+async fn find_lead_image(client: &Client, url: &Url, source: &Url, page: &OnceCell<Option<Page>>, html: &str, metadata: &ArticleMetadata) -> Option<Url> {
+    let resolve = |base: &Url, src: &str| base.join(src.trim()).ok()
+        .filter(|image| image.scheme() == "http" || image.scheme() == "https");
+
+    if let Some(image) = metadata.image.as_deref().and_then(|src| resolve(source, src)) {
+        return Some(image);
+    }
+    if let Some(image) = html::lead_image(html).as_deref().and_then(|src| resolve(source, src)) {
+        return Some(image);
+    }
+    get_page(page, client, url).await
+        .and_then(|page| page.meta("og:image"))
+        .as_deref()
+        .and_then(|src| resolve(url, src))
+}
+
+/// Guess the mime type of an image from the extension of its url
+fn image_mime_type(image: &Url) -> &'static str {
+    let extension = image.path().rsplit_once('.')
+        .map(|(_, extension)| extension.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("png") => "image/png",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("avif") => "image/avif",
+        Some("svg") => "image/svg+xml",
+        _ => "image/jpeg",
+    }
+}
+
+/// Detect the language of `item` from its `html`, tagging it as `dc:language` if requested
+///
+/// Returns whether the item passes the language filter, judged by the declared language
+/// (of the item, else of the feed) where detection fails.
+fn tag_item_language(item: &mut rss::Item, html: &str, feed_language: Option<&str>, extraction_opts: &ExtractionOpts) -> bool {
+    let detected = extraction_opts.detect_language(html);
+    let declared = item_language(item).or(feed_language);
+    if !extraction_opts.accepts_language(detected.or(declared)) {
+        debug!("Dropping item {:?}, its language is not allowed", item.link());
+        return false;
+    }
+    if let Some(detected) = detected.filter(|_| extraction_opts.detect_language) {
+        let mut dc = item.dublin_core_ext().cloned().unwrap_or_default();
+        if dc.languages().is_empty() {
+            dc.set_languages(vec![detected.to_owned()]);
+            item.set_dublin_core_ext(dc);
+        }
+    }
+    true
+}
+
+/// Detect the language of `entry` from its `html`, tagging its content with `xml:lang` if requested
+///
+/// Atom entries carry no `xml:lang` of their own in `atom_syndication`, hence the content is tagged.
+/// Returns whether the entry passes the language filter, judged by the declared language
+/// (of the content, else of the feed) where detection fails.
+fn tag_entry_language(entry: &mut atom_syndication::Entry, html: &str, feed_language: Option<&str>, extraction_opts: &ExtractionOpts) -> bool {
+    let detected = extraction_opts.detect_language(html);
+    let declared = entry_language(entry).or(feed_language);
+    if !extraction_opts.accepts_language(detected.or(declared)) {
+        debug!("Dropping entry {:?}, its language is not allowed", entry.id());
+        return false;
+    }
+    if let Some(detected) = detected.filter(|_| extraction_opts.detect_language) {
+        if let Some(content) = entry.content.as_mut().filter(|content| content.lang.is_none()) {
+            content.lang = Some(detected.to_owned());
+        }
+    }
+    true
+}
+
+/// Move the content of `item` into the elements selected by `target`
+///
+/// `summary` (e.g. the original content with summary placement) becomes the
+/// description, unless the target puts the content there, in which case it is dropped.
+/// The summary target falls back to the description, else the `original` content of the item.
+fn write_rss_target(item: &mut rss::Item, target: RssTarget, summary: Option<String>, original: Option<&str>) {
+    match target {
+        RssTarget::Content => if summary.is_some() {
+            item.set_description(summary);
+        },
+        RssTarget::Description => if let Some(content) = item.content.take() {
+            item.set_description(content);
+        },
+        RssTarget::Both => if let Some(content) = item.content() {
+            item.set_description(content.to_owned());
+        },
+        RssTarget::Summary => {
+            let summary = summary.or_else(|| item.description().or(original).map(|s| s.to_owned()));
+            item.set_description(summary);
+        },
+    }
+}
+
+/// Replace the content of `item` by the rendering of `template`
+///
+/// `article` is `None` for items whose extraction failed.
+fn render_item(template: &ItemTemplate, item: &mut rss::Item, original: Option<&str>, article: Option<(&ExtractedArticle, ArticleLength)>) {
+    let author = item.author().map(|author| author.to_owned())
+        .or_else(|| item.dublin_core_ext()
+            .filter(|dc| !dc.creators().is_empty())
+            .map(|dc| dc.creators().join(", ")));
+    let url = get_item_link(item);
+    let content = item.content().or(original).unwrap_or("");
+    let rendered = template.render(&TemplateItem {
+        title: item.title(),
+        url: url.as_deref(),
+        author,
+        date: item.pub_date().map(|date| date.to_owned()),
+        content,
+        original,
+        full_text: article.map(|(article, _)| article.html.as_str()),
+        stage: article.map(|(article, _)| article.stage),
+        length: article.map(|(_, length)| length),
+    });
+    match rendered {
+        Ok(rendered) => item.set_content(Some(rendered)),
+        Err(e) => warn!("Failed to render template for {:?}: {:?}", url, e),
+    }
+}
+
+/// Replace the content of `entry` by the rendering of `template`
+///
+/// `article` is `None` for entries whose extraction failed.
+fn render_entry(template: &ItemTemplate, entry: &mut atom_syndication::Entry, original: Option<&str>, article: Option<(&ExtractedArticle, ArticleLength)>) {
+    let authors = entry.authors().iter().map(|person| person.name()).collect::<Vec<_>>();
+    let date = entry_date(entry).map(|date| date.to_rfc3339());
+    let url = get_alternate_link(entry);
+    let content = entry.content().and_then(|content| content.value()).or(original).unwrap_or("");
+    let rendered = template.render(&TemplateItem {
+        title: Some(entry.title().as_str()),
+        url: url.as_deref(),
+        author: (!authors.is_empty()).then(|| authors.join(", ")),
+        date,
+        content,
+        original,
+        full_text: article.map(|(article, _)| article.html.as_str()),
+        stage: article.map(|(article, _)| article.stage),
+        length: article.map(|(_, length)| length),
+    });
+    match rendered {
+        Ok(rendered) => {
+            let mut content = atom_syndication::Content::default();
+            content.set_value(Some(rendered));
+            content.set_content_type(Some("html".to_string()));
+            content.lang = entry_language(entry).map(str::to_owned);
+            entry.set_content(Some(content));
+        },
+        Err(e) => warn!("Failed to render template for {:?}: {:?}", url, e),
+    }
+}
+
+/// Annotate `item` with `ftr:wordCount` and `ftr:readingTime` elements
+fn attach_item_length(item: &mut rss::Item, length: &ArticleLength) {
+    let elements = length.elements().into_iter().map(|(name, value)| {
+        let mut extension = rss::extension::Extension::default();
+        extension.set_name(format!("{}:{}", FTR_NAMESPACE_PREFIX, name));
+        extension.set_value(value);
+        (name.to_owned(), vec![extension])
+    }).collect();
+    item.extensions.insert(FTR_NAMESPACE_PREFIX.to_owned(), elements);
+}
+
+/// Annotate `entry` with `ftr:wordCount` and `ftr:readingTime` elements
+fn attach_entry_length(entry: &mut atom_syndication::Entry, length: &ArticleLength) {
+    let elements = length.elements().into_iter().map(|(name, value)| {
+        let mut extension = atom_syndication::extension::Extension::default();
+        extension.set_name(format!("{}:{}", FTR_NAMESPACE_PREFIX, name));
+        extension.set_value(value);
+        (name.to_owned(), vec![extension])
+    }).collect();
+    entry.extensions.insert(FTR_NAMESPACE_PREFIX.to_owned(), elements);
+}
+
+/// Attach `image` to `item` as enclosure and Media RSS content/thumbnail,
+/// leaving existing ones untouched
+fn attach_item_image(item: &mut rss::Item, image: &Url) {
+    let mime_type = image_mime_type(image);
+    if item.enclosure().is_none() {
+        let mut enclosure = rss::Enclosure::default();
+        enclosure.set_url(image.as_str());
+        // The size is unknown without downloading the image
+        enclosure.set_length("0");
+        enclosure.set_mime_type(mime_type);
+        item.set_enclosure(enclosure);
+    }
+    if !item.extensions().contains_key("media") {
+        let media_element = |name: &str, attrs: &[(&str, &str)]| {
+            let mut extension = rss::extension::Extension::default();
+            extension.set_name(format!("media:{}", name));
+            extension.attrs = attrs.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect();
+            (name.to_owned(), vec![extension])
+        };
+        let media = [
+            media_element("content", &[("url", image.as_str()), ("medium", "image"), ("type", mime_type)]),
+            media_element("thumbnail", &[("url", image.as_str())]),
+        ].into_iter().collect();
+        item.extensions.insert("media".to_owned(), media);
+    }
+}
+
+/// Attach `image` to `entry` as `<link rel="enclosure">`, unless it already has an enclosure
+fn attach_entry_image(entry: &mut atom_syndication::Entry, image: &Url) {
+    if entry.links().iter().any(|link| link.rel() == "enclosure") {
+        return;
+    }
+    let mut link = atom_syndication::Link::default();
+    link.set_href(image.as_str());
+    link.set_rel("enclosure");
+    link.set_mime_type(Some(image_mime_type(image).to_owned()));
+    entry.links.push(link);
+}
+
+/// Html body of a structured data article, led by its image
+fn structured_body(structured: &StructuredArticle) -> Option<String> {
+    let body = structured.body.as_deref()?;
+    let body = if html::is_html(body) {
+        body.to_owned()
+    } else {
+        html::text_to_html(body)
+    };
+    match &structured.image {
+        Some(image) if !body.contains(image.as_str()) => {
+            Some(format!("<figure><img src=\"{}\"></figure>{}", quick_xml::escape::escape(image.as_str()), body))
+        },
+        _ => Some(body),
+    }
+}
+
+/// Fetch the page behind `url` once, remembering failures as well
+async fn get_page<'a>(page: &'a OnceCell<Option<Page>>, client: &Client, url: &Url) -> Option<&'a Page> {
+    page.get_or_init(|| async {
+        Page::fetch(client, url).await
+            .inspect_err(|e| debug!("Failed to fetch page {}: {:?}", url, e))
+            .ok()
+    }).await.as_ref()
+}
+
+/// Run `ArticleScraper` on `url`, guarding against panics within the scraper
+///
+/// Along with the content, returns the final url of the page, after redirects.
+async fn scrape(scraper: &ArticleScraper, client: &Client, url: &Url) -> Result<(String, ArticleMetadata, Url)> {
+    let article_result = AssertUnwindSafe(scraper.parse(url, false, client, None)).catch_unwind().await;
     let article = match article_result {
-        Err(_e) => return Err(Box::new(FeedError::NoArticleError(url_str.to_string()))),
+        Err(_e) => return Err(Box::new(FeedError::NoArticleError(url.to_string()))),
         Ok(a) => a
     }?;
     trace!("Fulltext: {:?}", article.html);
-    return article.html.ok_or(FeedError::NoArticleError(url_str.to_string()).into());
+    let metadata = ArticleMetadata {
+        title: article.title,
+        author: article.author,
+        published: article.date.map(|date| date.fixed_offset()),
+        updated: None,
+        image: article.thumbnail_url,
+    };
+    let html = article.html.ok_or(FeedError::NoArticleError(url.to_string()))?;
+    Ok((html, metadata, article.url))
+}
+
+/// Run readability on the raw page, guarding against panics
+async fn readability(page: &Page) -> Option<String> {
+    let result = AssertUnwindSafe(Readability::extract(&page.html, Some(page.url.clone()))).catch_unwind().await;
+    match result {
+        Ok(Ok(html)) => Some(html),
+        Ok(Err(e)) => {
+            debug!("Readability failed for {}: {:?}", page.url, e);
+            None
+        },
+        Err(_e) => None,
+    }
+}
+
+/// Try the alternate versions (AMP, canonical) of `url` configured for its domain
+///
+/// Returns the first extraction passing the `quality` threshold, along with the final url of its alternate.
+async fn scrape_alternates(scraper: &ArticleScraper, client: &Client, url: &Url, page: &OnceCell<Option<Page>>, extraction_opts: &ExtractionOpts, quality: &QualityThreshold) -> Option<(String, ArticleMetadata, Url)> {
+    let alternates = &extraction_opts.domain_conf(url)?.alternates;
+    if alternates.is_empty() {
+        return None;
+    }
+
+    let page = get_page(page, client, url).await?;
+    for alternate in alternates {
+        let Some(alternate_url) = page.link_rel(alternate.rel()) else {
+            continue;
+        };
+        if alternate_url == *url {
+            continue;
+        }
+        debug!("Trying {:?} alternate {} for {}", alternate, alternate_url, url);
+        match scrape(scraper, client, &alternate_url).await {
+            Ok((html, metadata, scraped_url)) if quality.accepts(&html) => return Some((html, metadata, scraped_url)),
+            Ok(_) => debug!("Alternate {} is below the quality threshold", alternate_url),
+            Err(e) => debug!("Failed to extract alternate {}: {:?}", alternate_url, e),
+        }
+    }
+    None
+}
+
+/// Apply the configured transformations to the extracted content
+///
+/// Relative urls within the content are resolved against `base`.
+async fn postprocess_article(client: &Client, mut article: ExtractedArticle, base: &Url, extraction_opts: &ExtractionOpts) -> ExtractedArticle {
+    debug!("Content for {} produced by stage {:?}", article.url, article.stage);
+    match html::fix_images(&article.html) {
+        Ok(html) => article.html = html,
+        Err(e) => warn!("Failed to fix images of {}: {:?}", article.url, e),
+    }
+    match html::absolutize_urls(&article.html, base) {
+        Ok(html) => article.html = html,
+        Err(e) => warn!("Failed to absolutize urls of {}: {:?}", article.url, e),
+    }
+    if extraction_opts.strip_tracking_params {
+        match html::map_urls(&article.html, |url| Some(extraction_opts.clean_url(url))) {
+            Ok(html) => article.html = html,
+            Err(e) => warn!("Failed to strip tracking parameters from {}: {:?}", article.url, e),
+        }
+    }
+    // Footnotes show the link targets, so they have to be cleaned up by now
+    let links = match extraction_opts.links {
+        LinkMode::Preserve => None,
+        LinkMode::Footnotes => Some(html::links_to_footnotes(&article.html)),
+        LinkMode::Remove => Some(html::remove_links(&article.html)),
+    };
+    match links {
+        Some(Ok(html)) => article.html = html,
+        Some(Err(e)) => warn!("Failed to rewrite links of {}: {:?}", article.url, e),
+        None => (),
+    }
+    // Embeds are converted after rewriting the links, so that their previews keep theirs
+    match embeds::convert_embeds(&article.html, extraction_opts.unknown_iframes) {
+        Ok(html) => article.html = html,
+        Err(e) => warn!("Failed to convert embeds of {}: {:?}", article.url, e),
+    }
+    if extraction_opts.inline_images {
+        match images::inline_images(client, &article.html, &extraction_opts.inline_limits).await {
+            Ok(html) => article.html = html,
+            Err(e) => warn!("Failed to inline images of {}: {:?}", article.url, e),
+        }
+    }
+    // Only the images which could not be inlined remain to be proxied
+    if let Some(proxy) = &extraction_opts.image_proxy {
+        let proxy_url = |url: &str| as_http_url(url).map(|url| proxy.proxy_url(&url).to_string());
+        match html::map_image_urls(&article.html, proxy_url) {
+            Ok(html) => article.html = html,
+            Err(e) => warn!("Failed to proxy images of {}: {:?}", article.url, e),
+        }
+        article.lead_image = article.lead_image.map(|image| proxy.proxy_url(image.as_str()));
+    }
+    // Sanitizing comes last, so that no other transformation reintroduces unsafe content
+    if extraction_opts.xss {
+        article.html = html::sanitize(&article.html, extraction_opts.allow_iframes);
+    }
+    article
+}
+
+/// Return `candidate` if it is an absolute http(s) url
+fn as_http_url(candidate: &str) -> Option<String> {
+    match Url::parse(candidate.trim()) {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => Some(url.to_string()),
+        _ => None,
+    }
+}
+
+/// Find the single absolute link within an html snippet (e.g. an item description)
+///
+/// Snippets linking to several pages (e.g. "via" or author links) yield none.
+fn single_anchor(html: &str) -> Option<String> {
+    let fragment = scraper::Html::parse_fragment(html);
+    let selector = scraper::Selector::parse("a[href]").unwrap();
+    let mut links = fragment.select(&selector)
+        .filter_map(|anchor| anchor.value().attr("href"))
+        .filter_map(as_http_url);
+    let link = links.next()?;
+    links.all(|other| other == link).then_some(link)
+}
+
+/// Determine the article url of an rss item
+///
+/// Fallback chain: `<link>`, a permalink `<guid>`, the single link in the description,
+/// each only if it is an absolute http url
+fn get_item_link(item: &rss::Item) -> Option<String> {
+    item.link().and_then(as_http_url)
+        .or_else(|| item.guid()
+            .filter(|guid| guid.is_permalink())
+            .and_then(|guid| as_http_url(guid.value())))
+        .or_else(|| item.description().and_then(single_anchor))
+}
+
+/// Convert an atom entry to an rss item, as far as its fields have an equivalent
+fn entry_to_item(entry: &atom_syndication::Entry) -> rss::Item {
+    let mut item = rss::Item::default();
+    item.set_title(entry.title().value.clone());
+    item.set_link(get_primary_link(entry));
+    item.set_guid(rss::Guid { value: entry.id().to_owned(), permalink: false });
+    item.set_pub_date(entry_date(entry).map(|date| date.to_rfc2822()));
+    if !entry.authors().is_empty() {
+        let mut dc = rss::extension::dublincore::DublinCoreExtension::default();
+        dc.set_creators(entry.authors().iter().map(|person| person.name().to_owned()).collect::<Vec<_>>());
+        item.set_dublin_core_ext(dc);
+    }
+    item.set_categories(entry.categories().iter().map(|category| rss::Category {
+        name: category.label().unwrap_or(category.term()).to_owned(),
+        domain: category.scheme().map(|scheme| scheme.to_owned()),
+    }).collect::<Vec<_>>());
+    item.set_description(entry.summary().map(|summary| summary.value.clone()));
+    item.set_content(entry.content().and_then(|content| content.value()).map(|value| value.to_owned()));
+    if let Some(link) = entry.links().iter().find(|link| link.rel() == "enclosure") {
+        let mut enclosure = rss::Enclosure::default();
+        enclosure.set_url(link.href());
+        enclosure.set_length(link.length().unwrap_or("0"));
+        enclosure.set_mime_type(link.mime_type().unwrap_or("application/octet-stream"));
+        item.set_enclosure(enclosure);
+    }
+    item
+}
+
+/// Publication date of an rss item, from `pubDate` or `dc:date`
+fn item_date(item: &rss::Item) -> Option<DateTime<FixedOffset>> {
+    item.pub_date()
+        .or_else(|| item.dublin_core_ext().and_then(|dc| dc.dates().first()).map(|date| date.as_str()))
+        .and_then(parse_date)
 }
 
-async fn item_to_article(scraper: &ArticleScraper, client: &Client, item: &rss::Item) -> Result<String> {
-    if let Some(url_str) = &item.link {
-        url_to_article(&scraper, &client, &url_str).await
+/// Publication date of an atom entry, falling back to its last update
+fn entry_date(entry: &atom_syndication::Entry) -> Option<DateTime<FixedOffset>> {
+    // atom_syndication defaults a missing <updated> to the unix epoch
+    entry.published().copied()
+        .or(Some(*entry.updated()).filter(|updated| updated.timestamp() != 0))
+}
+
+/// Language declared by an rss item as `dc:language`
+fn item_language(item: &rss::Item) -> Option<&str> {
+    item.dublin_core_ext()
+        .and_then(|dc| dc.languages().first())
+        .map(|language| language.as_str())
+}
+
+/// Language declared by an atom entry, as `xml:lang` of its content
+fn entry_language(entry: &atom_syndication::Entry) -> Option<&str> {
+    entry.content().and_then(|content| content.lang.as_deref())
+}
+
+/// The fields of an rss item matched by filters
+fn item_fields(item: &rss::Item) -> ItemFields<'_> {
+    let mut authors = item.author().into_iter().collect::<Vec<_>>();
+    if let Some(dc) = item.dublin_core_ext() {
+        authors.extend(dc.creators().iter().map(|creator| creator.as_str()));
+    }
+    ItemFields {
+        title: item.title(),
+        link: get_item_link(item),
+        authors,
+        categories: item.categories().iter().map(|category| category.name()).collect(),
+    }
+}
+
+/// The fields of an atom entry matched by filters
+fn entry_fields(entry: &atom_syndication::Entry) -> ItemFields<'_> {
+    ItemFields {
+        title: Some(entry.title().as_str()),
+        link: get_alternate_link(entry),
+        authors: entry.authors().iter().map(|person| person.name()).collect(),
+        categories: entry.categories().iter()
+            .flat_map(|category| std::iter::once(category.term()).chain(category.label()))
+            .collect(),
+    }
+}
+
+/// The content the feed provides for an rss item, preferring `content:encoded`
+fn item_original_content(item: &rss::Item) -> Option<&str> {
+    item.content().or(item.description())
+}
+
+async fn item_to_article(scraper: &ArticleScraper, client: &Client, item: &rss::Item, extraction_opts: &ExtractionOpts) -> Result<ExtractedArticle> {
+    if let Some(url_str) = get_item_link(item) {
+        url_to_article(scraper, client, &url_str, item_original_content(item), extraction_opts).await
     } else {
         Err(FeedError::NoUrlError.into())
     }
 }
 
-fn get_primary_link(entry: &atom_syndication::Entry) -> Option<String> {
-    entry.links().into_iter().filter(|link| {
+fn get_alternate_link(entry: &atom_syndication::Entry) -> Option<String> {
+    entry.links().iter().find(|link| {
         link.rel() == "alternate"
-    }).next().map(|l| {l.href().to_owned()})
+    }).map(|l| {l.href().to_owned()})
+}
+
+/// Point the alternate link of `entry` to `href`, adding one if necessary
+fn set_alternate_link(entry: &mut atom_syndication::Entry, href: &str) {
+    if let Some(link) = entry.links.iter_mut().find(|link| link.rel() == "alternate") {
+        link.set_href(href);
+    } else {
+        let mut link = atom_syndication::Link::default();
+        link.set_href(href);
+        entry.links.push(link);
+    }
+}
+
+/// Determine the article url of an atom entry
+///
+/// Fallback chain: the alternate link, the `<id>`, the single link in the summary or content,
+/// each only if it is an absolute http url
+fn get_primary_link(entry: &atom_syndication::Entry) -> Option<String> {
+    get_alternate_link(entry).as_deref().and_then(as_http_url)
+        .or_else(|| as_http_url(entry.id()))
+        .or_else(|| entry.summary().and_then(|summary| single_anchor(&summary.value)))
+        .or_else(|| entry.content().and_then(|content| content.value()).and_then(single_anchor))
+}
+
+/// The content the feed provides for an atom entry, preferring `<content>`
+fn entry_original_content(entry: &atom_syndication::Entry) -> Option<&str> {
+    match entry.content() {
+        Some(content) => Some(content.value().unwrap_or("")),
+        None => entry.summary().map(|summary| summary.value.as_str()),
+    }
 }
 
-async fn entry_to_article(scraper: &ArticleScraper, client: &Client, entry: &atom_syndication::Entry) -> Result<String> {
+async fn entry_to_article(scraper: &ArticleScraper, client: &Client, entry: &atom_syndication::Entry, extraction_opts: &ExtractionOpts) -> Result<ExtractedArticle> {
     if let Some(url_str) = get_primary_link(entry) {
-        url_to_article(&scraper, &client, &url_str).await
+        url_to_article(scraper, client, &url_str, entry_original_content(entry), extraction_opts).await
     } else {
         Err(FeedError::NoUrlError.into())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn min_text_length_counts_characters() {
+        let quality = QualityThreshold { min_text_length: 12, min_paragraphs: 0 };
+        // 11 characters, but 22 bytes
+        assert!(!quality.accepts("<p>äöüäöü äöüä</p>"));
+        assert!(quality.accepts("<p>äöüäöü äöüäö</p>"));
+    }
+
+    fn item(link: Option<&str>, guid: Option<(&str, bool)>, description: Option<&str>) -> rss::Item {
+        let mut item = rss::Item::default();
+        item.set_link(link.map(|link| link.to_owned()));
+        item.set_guid(guid.map(|(value, permalink)| rss::Guid { value: value.to_owned(), permalink }));
+        item.set_description(description.map(|description| description.to_owned()));
+        item
+    }
+
+    #[test]
+    fn item_link_prefers_link() {
+        let item = item(Some("https://example.org/a"), Some(("https://example.org/b", true)), None);
+        assert_eq!(get_item_link(&item).as_deref(), Some("https://example.org/a"));
+    }
+
+    #[test]
+    fn item_link_falls_back_to_permalink_guid() {
+        let item1 = item(Some("/a"), Some(("https://example.org/b", true)), None);
+        assert_eq!(get_item_link(&item1).as_deref(), Some("https://example.org/b"));
+        let item2 = item(None, Some(("https://example.org/b", false)), None);
+        assert_eq!(get_item_link(&item2), None);
+    }
+
+    #[test]
+    fn item_link_falls_back_to_single_link_in_description() {
+        let item1 = item(None, None, Some(r#"<a href="https://example.org/a">a</a> <a href="https://example.org/a">more</a>"#));
+        assert_eq!(get_item_link(&item1).as_deref(), Some("https://example.org/a"));
+        let item2 = item(None, None, Some(r#"<a href="https://example.org/a">a</a> via <a href="https://other.org/">b</a>"#));
+        assert_eq!(get_item_link(&item2), None);
+        let item3 = item(Some("mailto:a@example.org"), None, Some(r#"<a href="/a">a</a>"#));
+        assert_eq!(get_item_link(&item3), None);
+    }
+
+    #[test]
+    fn entry_link_falls_back_to_http_id() {
+        let mut entry = atom_syndication::Entry::default();
+        entry.set_id("https://example.org/a");
+        assert_eq!(get_primary_link(&entry).as_deref(), Some("https://example.org/a"));
+        entry.set_id("urn:uuid:1");
+        assert_eq!(get_primary_link(&entry), None);
+    }
+
+    #[test]
+    fn overwriting_metadata_replaces_all_authors() {
+        let metadata = ArticleMetadata { author: Some("Jane Doe".to_owned()), ..Default::default() };
+        let mut item = rss::Item::default();
+        item.set_author("editor@example.org (Editor)".to_owned());
+        metadata.fill_item(&mut item, false);
+        assert_eq!(item.author(), Some("editor@example.org (Editor)"));
+        assert!(item.dublin_core_ext().is_none());
+        metadata.fill_item(&mut item, true);
+        assert_eq!(item.author(), None);
+        assert_eq!(item.dublin_core_ext().unwrap().creators(), ["Jane Doe"]);
+    }
+}
@@ -0,0 +1,220 @@
+use regex::Regex;
+use std::collections::HashMap;
+
+use super::config::{FeedConf, FilterConf};
+use super::html;
+
+/// Compile the filters configured per feed, keyed by feed url
+pub fn compile_feed_filters(feeds: &HashMap<String, FeedConf>) -> Result<HashMap<String, ItemFilter>, regex::Error> {
+    feeds.iter()
+        .map(|(url, feed_conf)| Ok((url.clone(), ItemFilter::compile(feed_conf)?)))
+        .collect()
+}
+
+/// Regular expressions matched against the fields of an item
+#[derive(Debug, Clone, Default)]
+pub struct FieldPatterns {
+    pub title: Option<Regex>,
+    pub link: Option<Regex>,
+    pub author: Option<Regex>,
+    pub category: Option<Regex>,
+    /// Matched against the extracted text, or the original content if extraction fails
+    pub text: Option<Regex>,
+}
+
+impl FieldPatterns {
+    pub fn compile(conf: &FilterConf) -> Result<FieldPatterns, regex::Error> {
+        let compile = |pattern: &Option<String>| pattern.as_deref().map(Regex::new).transpose();
+        Ok(FieldPatterns {
+            title: compile(&conf.title)?,
+            link: compile(&conf.link)?,
+            author: compile(&conf.author)?,
+            category: compile(&conf.category)?,
+            text: compile(&conf.text)?,
+        })
+    }
+
+    /// The patterns of `self`, falling back to those of `fallback` field by field
+    fn or(self, fallback: &FieldPatterns) -> FieldPatterns {
+        FieldPatterns {
+            title: self.title.or_else(|| fallback.title.clone()),
+            link: self.link.or_else(|| fallback.link.clone()),
+            author: self.author.or_else(|| fallback.author.clone()),
+            category: self.category.or_else(|| fallback.category.clone()),
+            text: self.text.or_else(|| fallback.text.clone()),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.title.is_none() && self.link.is_none() && self.author.is_none()
+            && self.category.is_none() && self.text.is_none()
+    }
+
+    /// Whether any of the patterns on fields other than the text matches `item`
+    fn matches_metadata(&self, item: &ItemFields) -> bool {
+        let matches = |pattern: &Option<Regex>, values: &[&str]| {
+            pattern.as_ref().is_some_and(|pattern| values.iter().any(|value| pattern.is_match(value)))
+        };
+        matches(&self.title, item.title.as_slice())
+            || matches(&self.link, item.link.as_deref().as_slice())
+            || matches(&self.author, &item.authors)
+            || matches(&self.category, &item.categories)
+    }
+
+    fn matches_text(&self, text: &str) -> bool {
+        self.text.as_ref().is_some_and(|pattern| pattern.is_match(text))
+    }
+}
+
+/// The fields of an item known before extraction
+#[derive(Debug, Default)]
+pub struct ItemFields<'a> {
+    pub title: Option<&'a str>,
+    pub link: Option<String>,
+    pub authors: Vec<&'a str>,
+    pub categories: Vec<&'a str>,
+}
+
+/// Verdict on an item judged by its metadata
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verdict {
+    Drop,
+    /// Kept, unless its text is excluded
+    Keep,
+    /// Kept only if its text is included
+    NeedsText,
+}
+
+/// Rules keeping or dropping items
+///
+/// An item is kept if any of the `include` patterns (if there are any) and
+/// none of the `exclude` patterns match.
+#[derive(Debug, Clone, Default)]
+pub struct ItemFilter {
+    pub include: FieldPatterns,
+    pub exclude: FieldPatterns,
+}
+
+impl ItemFilter {
+    pub fn compile(conf: &FeedConf) -> Result<ItemFilter, regex::Error> {
+        Ok(ItemFilter {
+            include: FieldPatterns::compile(&conf.include)?,
+            exclude: FieldPatterns::compile(&conf.exclude)?,
+        })
+    }
+
+    /// The rules of `self`, falling back to those of `fallback` field by field
+    pub fn or(self, fallback: Option<&ItemFilter>) -> ItemFilter {
+        match fallback {
+            Some(fallback) => ItemFilter {
+                include: self.include.or(&fallback.include),
+                exclude: self.exclude.or(&fallback.exclude),
+            },
+            None => self,
+        }
+    }
+
+    /// Judge `item` by its metadata, before extracting it
+    pub fn check_metadata(&self, item: &ItemFields) -> Verdict {
+        if self.exclude.matches_metadata(item) {
+            Verdict::Drop
+        } else if self.include.is_empty() || self.include.matches_metadata(item) {
+            Verdict::Keep
+        } else if self.include.text.is_some() {
+            Verdict::NeedsText
+        } else {
+            Verdict::Drop
+        }
+    }
+
+    /// Judge an item, given its `verdict` by metadata, by the text of its `html`
+    pub fn accepts_html(&self, verdict: Verdict, html: &str) -> bool {
+        if verdict == Verdict::Drop {
+            return false;
+        }
+        if self.include.text.is_none() && self.exclude.text.is_none() {
+            return verdict == Verdict::Keep;
+        }
+        let text = html::text_content(html);
+        !self.exclude.matches_text(&text)
+            && (verdict == Verdict::Keep || self.include.matches_text(&text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(include: FilterConf, exclude: FilterConf) -> ItemFilter {
+        ItemFilter::compile(&FeedConf { include, exclude }).unwrap()
+    }
+
+    fn item<'a>(title: &'a str, categories: Vec<&'a str>) -> ItemFields<'a> {
+        ItemFields {
+            title: Some(title),
+            link: Some("https://example.org/a".to_owned()),
+            authors: vec!["Jane"],
+            categories,
+        }
+    }
+
+    #[test]
+    fn keeps_everything_without_patterns() {
+        let filter = ItemFilter::default();
+        assert_eq!(filter.check_metadata(&item("Anything", vec![])), Verdict::Keep);
+        assert!(filter.accepts_html(Verdict::Keep, "<p>text</p>"));
+    }
+
+    #[test]
+    fn excludes_by_metadata() {
+        let filter = filter(FilterConf::default(), FilterConf { author: Some("^Jane$".to_owned()), ..Default::default() });
+        assert_eq!(filter.check_metadata(&item("Title", vec![])), Verdict::Drop);
+        assert!(!filter.accepts_html(Verdict::Drop, "<p>text</p>"));
+    }
+
+    #[test]
+    fn includes_by_any_field() {
+        let filter = filter(FilterConf {
+            title: Some("(?i)rust".to_owned()),
+            category: Some("^Linux$".to_owned()),
+            ..Default::default()
+        }, FilterConf::default());
+        assert_eq!(filter.check_metadata(&item("Learning Rust", vec![])), Verdict::Keep);
+        assert_eq!(filter.check_metadata(&item("Kernel news", vec!["Linux"])), Verdict::Keep);
+        assert_eq!(filter.check_metadata(&item("Kernel news", vec!["Linux kernel"])), Verdict::Drop);
+    }
+
+    #[test]
+    fn include_text_only_filters_need_the_text() {
+        let filter = filter(FilterConf { text: Some("(?i)rust".to_owned()), ..Default::default() }, FilterConf::default());
+        let verdict = filter.check_metadata(&item("Anything", vec![]));
+        assert_eq!(verdict, Verdict::NeedsText);
+        assert!(filter.accepts_html(verdict, "<p>Written in <b>Rust</b></p>"));
+        assert!(!filter.accepts_html(verdict, "<p>Written in C</p>"));
+    }
+
+    #[test]
+    fn excluded_text_overrides_included_metadata() {
+        let filter = filter(
+            FilterConf { title: Some("Rust".to_owned()), ..Default::default() },
+            FilterConf { text: Some("(?i)sponsored".to_owned()), ..Default::default() },
+        );
+        let verdict = filter.check_metadata(&item("Rust news", vec![]));
+        assert_eq!(verdict, Verdict::Keep);
+        assert!(filter.accepts_html(verdict, "<p>News</p>"));
+        assert!(!filter.accepts_html(verdict, "<p>Sponsored post</p>"));
+    }
+
+    #[test]
+    fn falls_back_field_by_field() {
+        let configured = filter(
+            FilterConf { title: Some("Rust".to_owned()), ..Default::default() },
+            FilterConf { author: Some("Jane".to_owned()), ..Default::default() },
+        );
+        let query = filter(FilterConf { title: Some("Go".to_owned()), ..Default::default() }, FilterConf::default())
+            .or(Some(&configured));
+        assert_eq!(query.check_metadata(&item("Rust news", vec![])), Verdict::Drop);
+        assert_eq!(query.check_metadata(&ItemFields { authors: vec!["Joe"], ..item("Go news", vec![]) }), Verdict::Keep);
+        assert_eq!(query.check_metadata(&item("Go news", vec![])), Verdict::Drop);
+    }
+}
@@ -0,0 +1,616 @@
+use lol_html::{element, text, rewrite_str, RewriteStrSettings};
+use lol_html::html_content::ContentType;
+use lol_html::errors::AttributeNameError;
+use lol_html::html_content::Element;
+use std::collections::HashMap;
+use url::Url;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Attributes holding a single url
+const URL_ATTRIBUTES: &[&str] = &["href", "src", "poster"];
+
+/// Read attribute `name` of `el`, resolving character references
+pub fn get_attribute(el: &Element, name: &str) -> Option<String> {
+    el.get_attribute(name).map(|raw| {
+        quick_xml::escape::unescape(&raw)
+            .map(|value| value.into_owned())
+            .unwrap_or(raw)
+    })
+}
+
+/// Set attribute `name` of `el` to `value`, escaping it as necessary
+pub fn set_attribute(el: &mut Element, name: &str, value: &str) -> std::result::Result<(), AttributeNameError> {
+    el.set_attribute(name, &value.replace('&', "&amp;"))
+}
+
+/// A single candidate of a `srcset` attribute
+#[derive(Debug, Clone, PartialEq)]
+pub struct SrcsetCandidate {
+    pub url: String,
+    /// Width (`640w`) or pixel density (`2x`) descriptor, if any
+    pub descriptor: Option<String>,
+}
+
+/// Split a `srcset` attribute into its candidates
+///
+/// Urls may contain commas (e.g. `data:` urls), so only commas following
+/// whitespace or descriptors separate candidates.
+pub fn parse_srcset(srcset: &str) -> Vec<SrcsetCandidate> {
+    let mut candidates = Vec::new();
+    let mut rest = srcset;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+        if rest.is_empty() {
+            return candidates;
+        }
+        let url_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let (url, tail) = rest.split_at(url_end);
+        let descriptor = if url.ends_with(',') {
+            rest = tail;
+            None
+        } else {
+            let descriptor_end = tail.find(',').unwrap_or(tail.len());
+            let (descriptor, tail) = tail.split_at(descriptor_end);
+            rest = tail;
+            Some(descriptor.trim()).filter(|descriptor| !descriptor.is_empty()).map(|descriptor| descriptor.to_owned())
+        };
+        candidates.push(SrcsetCandidate { url: url.trim_end_matches(',').to_owned(), descriptor });
+    }
+}
+
+/// Serialize `candidates` as `srcset` attribute
+pub fn format_srcset(candidates: &[SrcsetCandidate]) -> String {
+    candidates.iter()
+        .map(|candidate| match &candidate.descriptor {
+            Some(descriptor) => format!("{} {}", candidate.url, descriptor),
+            None => candidate.url.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Pass every url in `html`, i.e. url-carrying attributes and `srcset` candidates, through `map`
+///
+/// `map` returns `None` to leave a url untouched.
+pub fn map_urls<F>(html: &str, map: F) -> Result<String>
+    where F: Fn(&str) -> Option<String>
+{
+    let selector = URL_ATTRIBUTES.iter()
+        .chain(&["srcset"])
+        .map(|attr| format!("[{}]", attr))
+        .collect::<Vec<_>>()
+        .join(",");
+    rewrite_urls(html, &selector, URL_ATTRIBUTES, map)
+}
+
+/// Pass the sources (`src` and `srcset` candidates) of all images in `html` through `map`
+///
+/// `map` returns `None` to leave a url untouched.
+pub fn map_image_urls<F>(html: &str, map: F) -> Result<String>
+    where F: Fn(&str) -> Option<String>
+{
+    rewrite_urls(html, "img, picture source", &["src"], map)
+}
+
+/// Pass the `attributes` and `srcset` candidates of the elements matching `selector` through `map`
+fn rewrite_urls<F>(html: &str, selector: &str, attributes: &[&str], map: F) -> Result<String>
+    where F: Fn(&str) -> Option<String>
+{
+    let settings = RewriteStrSettings::new()
+        .append_element_content_handler(element!(selector, |el| {
+            for attr in attributes {
+                if let Some(new_value) = get_attribute(el, attr).and_then(|value| map(&value)) {
+                    set_attribute(el, attr, &new_value)?;
+                }
+            }
+            if let Some(srcset) = get_attribute(el, "srcset") {
+                let mut candidates = parse_srcset(&srcset);
+                let mut changed = false;
+                for candidate in candidates.iter_mut() {
+                    if let Some(url) = map(&candidate.url) {
+                        candidate.url = url;
+                        changed = true;
+                    }
+                }
+                if changed {
+                    set_attribute(el, "srcset", &format_srcset(&candidates))?;
+                }
+            }
+            Ok(())
+        }));
+    Ok(rewrite_str(html, settings)?)
+}
+
+/// Replace every link in `html` by its content
+pub fn remove_links(html: &str) -> Result<String> {
+    let settings = RewriteStrSettings::new()
+        .append_element_content_handler(element!("a[href]", |el| {
+            el.remove_and_keep_content();
+            Ok(())
+        }));
+    Ok(rewrite_str(html, settings)?)
+}
+
+/// Replace every link in `html` by its content followed by a numbered reference
+/// into a list of the link targets, appended to `html`
+///
+/// Links sharing a target share their number.
+pub fn links_to_footnotes(html: &str) -> Result<String> {
+    let targets = std::cell::RefCell::new(Vec::<String>::new());
+    let settings = RewriteStrSettings::new()
+        .append_element_content_handler(element!("a[href]", |el| {
+            let Some(href) = get_attribute(el, "href").map(|href| href.trim().to_owned()) else {
+                return Ok(());
+            };
+            let mut targets = targets.borrow_mut();
+            let number = match targets.iter().position(|target| *target == href) {
+                Some(index) => index + 1,
+                None => {
+                    targets.push(href);
+                    targets.len()
+                },
+            };
+            el.remove_and_keep_content();
+            el.after(&format!("<sup>[{}]</sup>", number), ContentType::Html);
+            Ok(())
+        }));
+    let mut html = rewrite_str(html, settings)?;
+
+    let targets = targets.into_inner();
+    if !targets.is_empty() {
+        html.push_str("<hr><ol>");
+        for target in targets {
+            let target = quick_xml::escape::escape(target.as_str());
+            html.push_str(&format!("<li><a href=\"{}\">{}</a></li>", target, target));
+        }
+        html.push_str("</ol>");
+    }
+    Ok(html)
+}
+
+/// Make every relative url in `html` absolute
+///
+/// Urls are resolved against the `<base href>` within `html`, if any, otherwise against `base`.
+pub fn absolutize_urls(html: &str, base: &Url) -> Result<String> {
+    let base = base_href(html)
+        .and_then(|href| base.join(&href).ok())
+        .unwrap_or_else(|| base.clone());
+    map_urls(html, |url| {
+        match Url::parse(url.trim()) {
+            Err(url::ParseError::RelativeUrlWithoutBase) => base.join(url.trim()).ok().map(|url| url.to_string()),
+            _ => None,
+        }
+    })
+}
+
+/// Target of the first `<base href>` in `html`
+pub fn base_href(html: &str) -> Option<String> {
+    let document = scraper::Html::parse_document(html);
+    let selector = scraper::Selector::parse("base[href]").unwrap();
+    document.select(&selector)
+        .find_map(|base| base.value().attr("href"))
+        .map(|href| href.trim().to_owned())
+        .filter(|href| !href.is_empty())
+}
+
+/// The human readable text of `html`, with whitespace collapsed
+pub fn text_content(html: &str) -> String {
+    let fragment = scraper::Html::parse_fragment(html);
+    fragment.root_element().text()
+        .flat_map(|chunk| chunk.split_whitespace())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Number of words of the human readable text of `html`
+pub fn word_count(html: &str) -> usize {
+    text_content(html).split_whitespace().count()
+}
+
+/// Number of non-empty paragraphs in `html`
+pub fn paragraph_count(html: &str) -> usize {
+    let fragment = scraper::Html::parse_fragment(html);
+    let selector = scraper::Selector::parse("p").unwrap();
+    fragment.select(&selector)
+        .filter(|p| p.text().any(|chunk| !chunk.trim().is_empty()))
+        .count()
+}
+
+/// Whitespace-normalized text of the non-empty paragraphs in `html`
+pub fn paragraph_texts(html: &str) -> Vec<String> {
+    let fragment = scraper::Html::parse_fragment(html);
+    let selector = scraper::Selector::parse("p").unwrap();
+    fragment.select(&selector)
+        .map(|p| p.text().flat_map(|chunk| chunk.split_whitespace()).collect::<Vec<_>>().join(" "))
+        .filter(|text| !text.is_empty())
+        .collect()
+}
+
+/// Convert plain text to html, turning blank-line separated blocks into paragraphs
+pub fn text_to_html(text: &str) -> String {
+    text.split("\n\n")
+        .map(|paragraph| paragraph.trim())
+        .filter(|paragraph| !paragraph.is_empty())
+        .map(|paragraph| format!("<p>{}</p>", quick_xml::escape::escape(paragraph)))
+        .collect()
+}
+
+/// Whether `text` contains html elements rather than just plain text
+pub fn is_html(text: &str) -> bool {
+    let fragment = scraper::Html::parse_fragment(text);
+    fragment.root_element().children()
+        .any(|child| child.value().is_element())
+}
+
+/// Attributes commonly holding the actual url of a lazy-loaded image
+const LAZY_SRC_ATTRIBUTES: &[&str] = &[
+    "data-src", "data-lazy-src", "data-original", "data-lazy", "data-url", "data-hi-res-src", "data-actualsrc",
+];
+
+/// Attributes commonly holding the actual `srcset` of a lazy-loaded image
+const LAZY_SRCSET_ATTRIBUTES: &[&str] = &["data-srcset", "data-lazy-srcset"];
+
+/// Attribute temporarily marking the images unwrapped from `<noscript>`
+const NOSCRIPT_MARKER: &str = "data-ftr-noscript";
+
+/// Width preferred when picking an image from a `srcset`
+const PREFERRED_IMAGE_WIDTH: f32 = 1200.0;
+
+/// Pick a sensible candidate from a `srcset`
+///
+/// For width descriptors, the largest candidate up to `PREFERRED_IMAGE_WIDTH` is preferred,
+/// for density descriptors the largest one up to `2x`. Otherwise the smallest candidate is taken.
+pub fn pick_srcset_candidate(candidates: &[SrcsetCandidate]) -> Option<&SrcsetCandidate> {
+    let widths = candidates.iter()
+        .filter_map(|candidate| {
+            let width = candidate.descriptor.as_deref()?.strip_suffix('w')?.parse::<f32>().ok()?;
+            Some((width, candidate))
+        })
+        .collect::<Vec<_>>();
+    let (sizes, limit) = if widths.is_empty() {
+        let densities = candidates.iter()
+            .filter_map(|candidate| {
+                let density = match candidate.descriptor.as_deref() {
+                    None => 1.0,
+                    Some(descriptor) => descriptor.strip_suffix('x')?.parse::<f32>().ok()?,
+                };
+                Some((density, candidate))
+            })
+            .collect::<Vec<_>>();
+        (densities, 2.0)
+    } else {
+        (widths, PREFERRED_IMAGE_WIDTH)
+    };
+
+    let by_size = |a: &&(f32, &SrcsetCandidate), b: &&(f32, &SrcsetCandidate)| a.0.total_cmp(&b.0);
+    sizes.iter().filter(|(size, _)| *size <= limit).max_by(by_size)
+        .or_else(|| sizes.iter().min_by(by_size))
+        .map(|(_, candidate)| *candidate)
+}
+
+/// Whether the `src` of an image is missing or, if `has_alternative`, likely a placeholder
+fn is_placeholder_src(src: Option<&str>, has_alternative: bool) -> bool {
+    match src.map(|src| src.trim()) {
+        None | Some("") => true,
+        Some(src) => has_alternative && (src.starts_with("data:") || src.contains("blank.gif") || src.contains("spacer.gif") || src.contains("placeholder")),
+    }
+}
+
+/// Whether `el` declares itself to be at most 1x1 pixels, as tracking pixels do
+fn is_tracking_pixel(el: &Element) -> bool {
+    ["width", "height"].iter().all(|dimension| {
+        get_attribute(el, dimension)
+            .and_then(|size| size.trim().trim_end_matches("px").parse::<u32>().ok())
+            .is_some_and(|size| size <= 1)
+    })
+}
+
+/// Repair the images of `html`
+///
+/// Lazy-loading attributes (e.g. `data-src`, also of `<picture>` sources) replace
+/// placeholder sources, images within `<noscript>` are unwrapped unless they repeat
+/// the image right before them, images lacking a `src` get one picked from their
+/// `srcset` and tracking pixels are removed.
+pub fn fix_images(html: &str) -> Result<String> {
+    // <noscript> content is raw text, only unwrap it when it carries an image
+    let noscript_content = std::cell::RefCell::new(String::new());
+    let settings = RewriteStrSettings::new()
+        .append_element_content_handler(element!("noscript", |el| {
+            el.remove_and_keep_content();
+            Ok(())
+        }))
+        .append_element_content_handler(text!("noscript", |chunk| {
+            let mut content = noscript_content.borrow_mut();
+            content.push_str(chunk.as_str());
+            if chunk.last_in_text_node() {
+                if content.contains("<img") {
+                    let marked = rewrite_str(&content, RewriteStrSettings::new()
+                        .append_element_content_handler(element!("img", |el| {
+                            el.set_attribute(NOSCRIPT_MARKER, "")?;
+                            Ok(())
+                        })))?;
+                    chunk.replace(&marked, ContentType::Html);
+                } else {
+                    chunk.remove();
+                }
+                content.clear();
+            } else {
+                chunk.remove();
+            }
+            Ok(())
+        }));
+    let html = rewrite_str(html, settings)?;
+
+    // The unwrapped images usually follow a placeholder with the same source
+    let previous_src = std::cell::RefCell::new(None::<String>);
+    let settings = RewriteStrSettings::new()
+        .append_element_content_handler(element!("picture source", |el| {
+            let lazy_srcset = LAZY_SRCSET_ATTRIBUTES.iter()
+                .find_map(|attr| get_attribute(el, attr))
+                .filter(|srcset| !srcset.trim().is_empty());
+            if let Some(srcset) = lazy_srcset {
+                set_attribute(el, "srcset", &srcset)?;
+            }
+            Ok(())
+        }))
+        .append_element_content_handler(element!("img", |el| {
+            let from_noscript = el.has_attribute(NOSCRIPT_MARKER);
+            el.remove_attribute(NOSCRIPT_MARKER);
+            if is_tracking_pixel(el) {
+                el.remove();
+                return Ok(());
+            }
+
+            let lazy_src = LAZY_SRC_ATTRIBUTES.iter()
+                .find_map(|attr| get_attribute(el, attr))
+                .filter(|src| !src.trim().is_empty());
+            let lazy_srcset = LAZY_SRCSET_ATTRIBUTES.iter()
+                .find_map(|attr| get_attribute(el, attr))
+                .filter(|srcset| !srcset.trim().is_empty());
+            let has_alternative = lazy_src.is_some() || lazy_srcset.is_some();
+
+            if let Some(srcset) = &lazy_srcset {
+                set_attribute(el, "srcset", srcset)?;
+            }
+            let mut src = get_attribute(el, "src");
+            if is_placeholder_src(src.as_deref(), has_alternative) {
+                src = lazy_src.or_else(|| {
+                    let srcset = get_attribute(el, "srcset")?;
+                    pick_srcset_candidate(&parse_srcset(&srcset)).map(|candidate| candidate.url.clone())
+                });
+                match &src {
+                    Some(src) => set_attribute(el, "src", src)?,
+                    // Nothing to show at all
+                    None => {
+                        el.remove();
+                        return Ok(());
+                    },
+                }
+            }
+
+            let mut previous_src = previous_src.borrow_mut();
+            if from_noscript && src.is_some() && *previous_src == src {
+                el.remove();
+            } else {
+                *previous_src = src;
+            }
+            Ok(())
+        }));
+    Ok(rewrite_str(&html, settings)?)
+}
+
+/// The `src` of every image in `html`, in document order
+pub fn image_sources(html: &str) -> Vec<String> {
+    let fragment = scraper::Html::parse_fragment(html);
+    let selector = scraper::Selector::parse("img[src]").unwrap();
+    fragment.select(&selector)
+        .filter_map(|img| img.value().attr("src"))
+        .map(|src| src.trim().to_owned())
+        .collect()
+}
+
+/// Replace image sources according to `sources`, keyed by the current `src`
+///
+/// The `srcset` of replaced images and the `<source>`s of their pictures are dropped,
+/// so that readers do not prefer them over the new `src`.
+pub fn replace_image_sources(html: &str, sources: &HashMap<String, String>) -> Result<String> {
+    if sources.is_empty() {
+        return Ok(html.to_owned());
+    }
+    // The <source>s precede the image of their picture, so determine up front
+    // which pictures have their image replaced
+    let fragment = scraper::Html::parse_fragment(html);
+    let picture_selector = scraper::Selector::parse("picture").unwrap();
+    let image_selector = scraper::Selector::parse("img[src]").unwrap();
+    let replaced_pictures = fragment.select(&picture_selector)
+        .map(|picture| picture.select(&image_selector)
+            .any(|img| img.value().attr("src").is_some_and(|src| sources.contains_key(src.trim()))))
+        .collect::<Vec<_>>();
+    let picture_index = std::cell::Cell::new(None::<usize>);
+
+    let settings = RewriteStrSettings::new()
+        .append_element_content_handler(element!("picture", |_el| {
+            picture_index.set(Some(picture_index.get().map_or(0, |index| index + 1)));
+            Ok(())
+        }))
+        .append_element_content_handler(element!("img[src]", |el| {
+            if let Some(new_src) = get_attribute(el, "src").and_then(|src| sources.get(src.trim())) {
+                set_attribute(el, "src", new_src)?;
+                el.remove_attribute("srcset");
+                el.remove_attribute("sizes");
+            }
+            Ok(())
+        }))
+        .append_element_content_handler(element!("picture source", |el| {
+            if picture_index.get().and_then(|index| replaced_pictures.get(index)).copied().unwrap_or(false) {
+                el.remove();
+            }
+            Ok(())
+        }));
+    Ok(rewrite_str(html, settings)?)
+}
+
+/// Images smaller than this (in either dimension, when declared) are not considered lead images
+const MIN_LEAD_IMAGE_SIZE: u32 = 200;
+
+/// The `src` of the first image in `html` which is not declared to be small
+pub fn lead_image(html: &str) -> Option<String> {
+    let fragment = scraper::Html::parse_fragment(html);
+    let selector = scraper::Selector::parse("img[src]").unwrap();
+    fragment.select(&selector)
+        .filter(|img| {
+            ["width", "height"].iter()
+                .filter_map(|dimension| img.value().attr(dimension))
+                .filter_map(|size| size.trim().trim_end_matches("px").parse::<u32>().ok())
+                .all(|size| size >= MIN_LEAD_IMAGE_SIZE)
+        })
+        .filter_map(|img| img.value().attr("src"))
+        .find(|src| !src.trim().is_empty() && !src.trim_start().starts_with("data:"))
+        .map(|src| src.to_owned())
+}
+
+/// Sanitize `html` against an allowlist of tags and attributes
+///
+/// Scripts, styles, event handlers and urls with unsafe schemes (e.g. `javascript:`)
+/// are removed, iframes are only kept when `allow_iframes` is set.
+pub fn sanitize(html: &str, allow_iframes: bool) -> String {
+    let mut builder = ammonia::Builder::default();
+    builder
+        .add_tags(&["picture", "source", "video", "audio", "main", "section"])
+        .add_tag_attributes("img", &["srcset", "sizes"])
+        .add_tag_attributes("source", &["src", "srcset", "sizes", "type", "media"])
+        .add_tag_attributes("video", &["src", "poster", "controls", "width", "height"])
+        .add_tag_attributes("audio", &["src", "controls"]);
+    // Inlined images are the only place data urls are accepted
+    builder
+        .add_url_schemes(&["data"])
+        .attribute_filter(|element, attribute, value| {
+            let is_data_url = value.trim_start().get(..5).is_some_and(|scheme| scheme.eq_ignore_ascii_case("data:"));
+            if is_data_url && !(element == "img" && attribute == "src" && value.trim_start()[5..].starts_with("image/")) {
+                None
+            } else {
+                Some(value.into())
+            }
+        });
+    if allow_iframes {
+        builder
+            .add_tags(&["iframe"])
+            .add_tag_attributes("iframe", &["src", "width", "height", "allowfullscreen", "title"]);
+    }
+    builder.clean(html).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(url: &str, descriptor: Option<&str>) -> SrcsetCandidate {
+        SrcsetCandidate { url: url.to_owned(), descriptor: descriptor.map(|descriptor| descriptor.to_owned()) }
+    }
+
+    #[test]
+    fn parses_srcset_descriptors() {
+        assert_eq!(parse_srcset("a.jpg 640w, b.jpg 1280w"), vec![candidate("a.jpg", Some("640w")), candidate("b.jpg", Some("1280w"))]);
+        assert_eq!(parse_srcset(" a.jpg, b.jpg 2x "), vec![candidate("a.jpg", None), candidate("b.jpg", Some("2x"))]);
+        // As per the spec, a comma only separates candidates after whitespace or at the end of a url
+        assert_eq!(parse_srcset("a.jpg,b.jpg 2x"), vec![candidate("a.jpg,b.jpg", Some("2x"))]);
+        assert_eq!(parse_srcset(""), vec![]);
+    }
+
+    #[test]
+    fn parses_srcset_urls_containing_commas() {
+        assert_eq!(
+            parse_srcset("data:image/png;base64,iVBORw0KGgo= 1x, /img,large.jpg 2x"),
+            vec![candidate("data:image/png;base64,iVBORw0KGgo=", Some("1x")), candidate("/img,large.jpg", Some("2x"))],
+        );
+    }
+
+    #[test]
+    fn absolutizes_relative_urls() {
+        let base = Url::parse("https://example.org/news/article.html").unwrap();
+        assert_eq!(
+            absolutize_urls(r#"<a href="../about">a</a><img src="a.jpg" srcset="b.jpg 2x">"#, &base).unwrap(),
+            r#"<a href="https://example.org/about">a</a><img src="https://example.org/news/a.jpg" srcset="https://example.org/news/b.jpg 2x">"#,
+        );
+        assert_eq!(
+            absolutize_urls(r#"<base href="/amp/"><img src="a.jpg">"#, &base).unwrap(),
+            r#"<base href="https://example.org/amp/"><img src="https://example.org/amp/a.jpg">"#,
+        );
+    }
+
+    #[test]
+    fn fixes_lazy_images() {
+        assert_eq!(
+            fix_images(r#"<img src="data:image/gif;base64,R0lG" data-src="a.jpg">"#).unwrap(),
+            r#"<img src="a.jpg" data-src="a.jpg">"#,
+        );
+        assert_eq!(
+            fix_images(r#"<picture><source data-srcset="a.webp 1x"><img src="a.jpg"></picture>"#).unwrap(),
+            r#"<picture><source data-srcset="a.webp 1x" srcset="a.webp 1x"><img src="a.jpg"></picture>"#,
+        );
+    }
+
+    #[test]
+    fn drops_noscript_copies_of_the_previous_image() {
+        assert_eq!(
+            fix_images(r#"<img data-src="a.jpg"><noscript><img src="a.jpg"></noscript>"#).unwrap(),
+            r#"<img data-src="a.jpg" src="a.jpg">"#,
+        );
+        assert_eq!(
+            fix_images(r#"<img src="a.jpg"><noscript><img src="b.jpg"></noscript>"#).unwrap(),
+            r#"<img src="a.jpg"><img src="b.jpg">"#,
+        );
+    }
+
+    #[test]
+    fn keeps_repeated_images() {
+        let html = r#"<p><img src="a.jpg"></p><p><img src="a.jpg"></p>"#;
+        assert_eq!(fix_images(html).unwrap(), html);
+    }
+
+    #[test]
+    fn removes_tracking_pixels() {
+        assert_eq!(fix_images(r#"<p>a<img src="t.gif" width="1" height="1px"></p>"#).unwrap(), "<p>a</p>");
+    }
+
+    #[test]
+    fn replaces_image_sources_of_inlined_images_only() {
+        let sources = HashMap::from([("a.jpg".to_owned(), "data:image/jpeg;base64,AA".to_owned())]);
+        assert_eq!(
+            replace_image_sources(
+                r#"<picture><source srcset="a.webp"><img src="a.jpg" srcset="a2.jpg 2x"></picture><picture><source srcset="b.webp"><img src="b.jpg"></picture>"#,
+                &sources,
+            ).unwrap(),
+            r#"<picture><img src="data:image/jpeg;base64,AA"></picture><picture><source srcset="b.webp"><img src="b.jpg"></picture>"#,
+        );
+    }
+
+    #[test]
+    fn sanitize_removes_scripts_and_event_handlers() {
+        assert_eq!(sanitize(r#"<p>a<script>alert(1)</script></p>"#, false), "<p>a</p>");
+        assert_eq!(sanitize(r#"<img src="a.jpg" onerror="alert(1)">"#, false), r#"<img src="a.jpg">"#);
+    }
+
+    #[test]
+    fn sanitize_removes_unsafe_urls() {
+        assert_eq!(sanitize(r#"<a href="javascript:alert(1)">a</a>"#, false), r#"<a rel="noopener noreferrer">a</a>"#);
+        assert_eq!(sanitize(r#"<a href="JavaScript:alert(1)">a</a>"#, false), r#"<a rel="noopener noreferrer">a</a>"#);
+    }
+
+    #[test]
+    fn sanitize_only_keeps_data_urls_of_image_sources() {
+        assert_eq!(sanitize(r#"<img src="data:image/png;base64,AA">"#, false), r#"<img src="data:image/png;base64,AA">"#);
+        assert_eq!(sanitize(r#"<a href="data:text/html;base64,AA">a</a>"#, false), r#"<a rel="noopener noreferrer">a</a>"#);
+        assert_eq!(sanitize(r#"<img src="data:text/html;base64,AA">"#, false), "<img>");
+        assert_eq!(sanitize(r#"<img srcset="data:image/png;base64,AA 2x">"#, false), "<img>");
+    }
+
+    #[test]
+    fn sanitize_keeps_iframes_only_if_allowed() {
+        let iframe = r#"<iframe src="https://example.org/embed" width="640"></iframe>"#;
+        assert_eq!(sanitize(iframe, false), "");
+        assert_eq!(sanitize(iframe, true), iframe);
+        assert_eq!(sanitize(r#"<iframe src="javascript:alert(1)"></iframe>"#, true), "<iframe></iframe>");
+    }
+}
@@ -0,0 +1,151 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use futures::StreamExt;
+use image::{DynamicImage, ImageFormat, ImageReader};
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use reqwest::Client;
+use std::collections::{HashMap, HashSet};
+use std::io::Cursor;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::html;
+
+use log::*;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Upper bound on the size of images downloaded for re-encoding
+const MAX_DOWNLOAD_SIZE: usize = 20 * 1024 * 1024;
+
+/// Quality of re-encoded JPEG images
+const JPEG_QUALITY: u8 = 80;
+
+/// Upper bound on the number of images of an item downloaded at once
+const MAX_CONCURRENT_DOWNLOADS: usize = 4;
+
+/// Caps on images embedded into an item
+#[derive(Debug, Clone, Copy)]
+pub struct InlineImageLimits {
+    /// Maximum size of a single embedded image in bytes
+    pub max_size: usize,
+    /// Maximum size of all images embedded into a single item in bytes
+    pub max_total: usize,
+    /// Images wider than this are downscaled and re-encoded, as are images exceeding `max_size`
+    pub max_width: Option<u32>,
+}
+
+/// Embed the images of `html` as data urls, within `limits`
+///
+/// Images which fail to download or exceed the limits keep their url.
+/// Images are only downloaded while the budget of `limits.max_total` is not used up.
+pub async fn inline_images(client: &Client, html: &str, limits: &InlineImageLimits) -> Result<String> {
+    let mut seen = HashSet::new();
+    let mut sources = html::image_sources(html);
+    sources.retain(|src| (src.starts_with("http://") || src.starts_with("https://")) && seen.insert(src.clone()));
+
+    // Budget the total in document order, so the leading images are the ones embedded
+    let total = AtomicUsize::new(0);
+    let mut images = futures::stream::iter(sources)
+        .map(|src| {
+            let total = &total;
+            async move {
+                if total.load(Ordering::Relaxed) >= limits.max_total {
+                    return (src, None);
+                }
+                let image = prepare_image(client, &src, limits).await
+                    .inspect_err(|e| debug!("Failed to inline image {}: {:?}", src, e))
+                    .ok()
+                    .flatten();
+                (src, image)
+            }
+        })
+        .buffered(MAX_CONCURRENT_DOWNLOADS);
+
+    let mut data_urls = HashMap::new();
+    while let Some((src, image)) = images.next().await {
+        let Some((mime_type, data)) = image else {
+            continue;
+        };
+        if total.load(Ordering::Relaxed) + data.len() > limits.max_total {
+            debug!("Not inlining {}, the item exceeds the total size limit", src);
+            continue;
+        }
+        total.fetch_add(data.len(), Ordering::Relaxed);
+        data_urls.insert(src, format!("data:{};base64,{}", mime_type, STANDARD.encode(&data)));
+    }
+    html::replace_image_sources(html, &data_urls)
+}
+
+/// Download the image at `src`, re-encoding it as necessary to meet `limits`
+///
+/// Returns the mime type and data of the image, `None` if it cannot be made to fit.
+async fn prepare_image(client: &Client, src: &str, limits: &InlineImageLimits) -> Result<Option<(String, Vec<u8>)>> {
+    let download_limit = if limits.max_width.is_some() {
+        MAX_DOWNLOAD_SIZE.max(limits.max_size)
+    } else {
+        limits.max_size
+    };
+
+    let response = client.get(src).send()
+        .await?
+        .error_for_status()?;
+    let mime_type = response.headers().get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .map(|value| value.trim().to_ascii_lowercase())
+        .unwrap_or_default();
+    if !mime_type.starts_with("image/") {
+        return Err(format!("unexpected content type {:?}", mime_type).into());
+    }
+    if response.content_length().is_some_and(|length| length as usize > download_limit) {
+        return Ok(None);
+    }
+
+    let mut data = Vec::new();
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        data.extend_from_slice(&chunk?);
+        if data.len() > download_limit {
+            return Ok(None);
+        }
+    }
+
+    let Some(max_width) = limits.max_width else {
+        return Ok((data.len() <= limits.max_size).then_some((mime_type, data)));
+    };
+    let max_size = limits.max_size;
+    let reencoded = tokio::task::spawn_blocking(move || downscale(data, max_width, max_size).map(|(reencoded_type, data)| {
+        (reencoded_type.map(|t| t.to_owned()).unwrap_or(mime_type), data)
+    })).await??;
+    Ok((reencoded.1.len() <= limits.max_size).then_some(reencoded))
+}
+
+/// Downscale `data` to `max_width`, re-encoding it if it is too wide or larger than `max_size`
+///
+/// Returns the new mime type, if the image has been re-encoded, along with the image data.
+fn downscale(data: Vec<u8>, max_width: u32, max_size: usize) -> Result<(Option<&'static str>, Vec<u8>)> {
+    // Formats unknown to `image` (e.g. SVG) are left as they are
+    let dimensions = ImageReader::new(Cursor::new(&data)).with_guessed_format()
+        .map_err(image::ImageError::from)
+        .and_then(|reader| reader.into_dimensions());
+    let width = match dimensions {
+        Ok((width, _)) if width > max_width || data.len() > max_size => width,
+        _ => return Ok((None, data)),
+    };
+
+    let mut image = ImageReader::new(Cursor::new(&data)).with_guessed_format()?.decode()?;
+    if width > max_width {
+        image = image.resize(max_width, u32::MAX, FilterType::Triangle);
+    }
+
+    let mut encoded = Vec::new();
+    if image.color().has_alpha() {
+        image.write_to(&mut Cursor::new(&mut encoded), ImageFormat::Png)?;
+        Ok((Some("image/png"), encoded))
+    } else {
+        let encoder = JpegEncoder::new_with_quality(&mut encoded, JPEG_QUALITY);
+        DynamicImage::ImageRgb8(image.to_rgb8()).write_with_encoder(encoder)?;
+        Ok((Some("image/jpeg"), encoded))
+    }
+}
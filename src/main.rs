@@ -1,15 +1,28 @@
 use article_scraper::ArticleScraper;
 use log::*;
+use std::sync::Arc;
 
 mod cli;
 mod config;
+mod dedup;
+mod embeds;
 mod feeds;
+mod filters;
+mod html;
+mod images;
+mod language;
+mod page;
+mod proxy;
+mod structured;
+mod summary;
+mod template;
+mod urls;
 mod webserver;
 
 use cli::Command;
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     // Parse CLI Options
     let cli_opts = cli::init();
     trace!("Parsed CLI options: {:?}", cli_opts);
@@ -31,24 +44,44 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Create a properly configured ArticleScraper instance
     match cli_opts.cmd {
         Command::Serve {} => {
-            webserver::serve(
-                conf.listen,
-                conf.fulltext_rss_filters,
-                conf.extraction_defaults,
-                conf.extraction_limits).await?;
+            webserver::serve(conf).await?;
         },
-        Command::MakeFulltext { url } => {
+        Command::MakeFulltext { urls, links } => {
             let scraper = ArticleScraper::new(conf.fulltext_rss_filters.get_custom_filterpath().as_deref()).await;
 
-            let extract_conf : feeds::ExtractionOpts = conf.extraction_defaults.into();
+            let proxy_images = conf.extraction_defaults.proxy_images;
+            let template = conf.extraction_defaults.template.as_deref()
+                .map(template::ItemTemplate::load)
+                .transpose()?;
+            let mut extract_conf : feeds::ExtractionOpts = conf.extraction_defaults.into();
+            extract_conf.domains = Arc::new(conf.domains);
+            extract_conf.template = template.map(Arc::new);
+            let feed_filters = filters::compile_feed_filters(&conf.feeds)?;
+            if let [url] = urls.as_slice() {
+                if let Some(filter) = feed_filters.get(url) {
+                    extract_conf.filter = filter.clone();
+                }
+            }
+            if let Some(links) = links {
+                extract_conf.links = links;
+            }
+            if proxy_images {
+                extract_conf.image_proxy = conf.image_proxy.build()?.map(Arc::new);
+                if extract_conf.image_proxy.is_none() {
+                    warn!("proxy_images requires image_proxy.secret and image_proxy.public_url to be set");
+                }
+            }
             let effective = extract_conf.bound_by_limits(&conf.extraction_limits);
-            let feed_res = feeds::get_fulltext_feed(&scraper, &url, &effective).await;
+            let feed_res = match urls.as_slice() {
+                [url] => feeds::get_fulltext_feed(&scraper, url, &effective).await,
+                urls => feeds::get_merged_fulltext_feed(&scraper, urls, &conf.merge, &feed_filters, &effective).await,
+            };
             match feed_res {
                 Ok(feed) => {
                     println!("{}", feed.to_string());
                 },
                 Err(e) => {
-                    return Err(e.into());
+                    return Err(e);
                 }
             }
         }
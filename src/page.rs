@@ -0,0 +1,125 @@
+use reqwest::Client;
+use scraper::{Html, Selector};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use url::Url;
+
+use super::html;
+use super::structured::{self, StructuredArticle};
+
+use log::*;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// The raw html of an article's web page, used to look up metadata
+/// that `ArticleScraper` does not expose
+#[derive(Debug)]
+pub struct Page {
+    /// Url the page was retrieved from, after following redirects
+    pub url: Url,
+    pub html: String,
+    /// What lookups need from `html`, parsed on the first one
+    parsed: OnceLock<Parsed>,
+}
+
+/// The `<meta>` and `<link>` tags and the structured data of a page
+#[derive(Debug, Default)]
+struct Parsed {
+    /// Content of the first non-empty `<meta>` tag by lowercase `property` or `name`
+    meta: HashMap<String, String>,
+    /// `rel` and `href` of the `<link>` tags, in document order
+    links: Vec<(String, String)>,
+    structured: Option<StructuredArticle>,
+}
+
+impl Page {
+    /// Retrieve the web page at `url`
+    pub async fn fetch(client: &Client, url: &Url) -> Result<Page> {
+        debug!("Fetching page: {}", url);
+        let response = client.get(url.clone()).send()
+            .await?
+            .error_for_status()?;
+        let url = response.url().clone();
+        let html = response.text().await?;
+        Ok(Page { url, html, parsed: OnceLock::new() })
+    }
+
+    /// Parse the document once for all lookups
+    fn parsed(&self) -> &Parsed {
+        self.parsed.get_or_init(|| {
+            let document = Html::parse_document(&self.html);
+            let mut parsed = Parsed::default();
+            let meta_selector = Selector::parse("meta[content]").unwrap();
+            for meta in document.select(&meta_selector) {
+                let value = meta.value();
+                let (Some(name), Some(content)) = (value.attr("property").or(value.attr("name")), value.attr("content")) else {
+                    continue;
+                };
+                let content = content.trim();
+                if !content.is_empty() {
+                    parsed.meta.entry(name.to_ascii_lowercase()).or_insert_with(|| content.to_owned());
+                }
+            }
+            let link_selector = Selector::parse("link[rel][href]").unwrap();
+            parsed.links = document.select(&link_selector)
+                .filter_map(|link| Some((link.value().attr("rel")?.to_owned(), link.value().attr("href")?.to_owned())))
+                .collect();
+            parsed.structured = structured::find_article(&document);
+            parsed
+        })
+    }
+
+    /// Resolve `href` relative to the page url, only accepting http(s) urls
+    fn resolve(&self, href: &str) -> Option<Url> {
+        self.url.join(href.trim()).ok()
+            .filter(|url| url.scheme() == "http" || url.scheme() == "https")
+    }
+
+    /// Url relative links of the page are resolved against, honouring `<base href>`
+    pub fn base_url(&self) -> Url {
+        html::base_href(&self.html)
+            .and_then(|href| self.url.join(&href).ok())
+            .unwrap_or_else(|| self.url.clone())
+    }
+
+    /// Target of the first `<link rel="{rel}">` in the page
+    pub fn link_rel(&self, rel: &str) -> Option<Url> {
+        self.parsed().links.iter()
+            .find(|(rels, _)| rels.split_ascii_whitespace().any(|r| r.eq_ignore_ascii_case(rel)))
+            .and_then(|(_, href)| self.resolve(href))
+    }
+
+    /// Content of the first non-empty `<meta>` tag whose `property` or `name` is `key`
+    pub fn meta(&self, key: &str) -> Option<String> {
+        self.parsed().meta.get(&key.to_ascii_lowercase()).cloned()
+    }
+
+    /// The article described by the page's structured data
+    pub fn structured_article(&self) -> Option<StructuredArticle> {
+        self.parsed().structured.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(html: &str) -> Page {
+        Page { url: Url::parse("https://example.org/a/b").unwrap(), html: html.to_owned(), parsed: OnceLock::new() }
+    }
+
+    #[test]
+    fn looks_up_meta_tags() {
+        let page = page(r#"<head><meta property="og:title" content=" "><meta name="OG:Title" content=" Title "><meta name="author" content="Jane"></head>"#);
+        assert_eq!(page.meta("og:title").as_deref(), Some("Title"));
+        assert_eq!(page.meta("Author").as_deref(), Some("Jane"));
+        assert_eq!(page.meta("og:image"), None);
+    }
+
+    #[test]
+    fn looks_up_link_rels() {
+        let page = page(r#"<head><link rel="stylesheet" href="s.css"><link rel="Canonical alternate" href="/c"><link rel="amphtml" href="javascript:x"></head>"#);
+        assert_eq!(page.link_rel("canonical").map(|url| url.to_string()).as_deref(), Some("https://example.org/c"));
+        assert_eq!(page.link_rel("amphtml"), None);
+    }
+}
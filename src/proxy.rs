@@ -0,0 +1,112 @@
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use hmac::{Hmac, KeyInit, Mac};
+use sha2::Sha256;
+use url::Url;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Route of the image proxy within the webserver
+pub const IMAGE_PROXY_PATH: &str = "/img";
+
+/// Upper bound on the time of retrieving a single proxied image
+pub const IMAGE_PROXY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// The secret of the example configuration, refused as it is publicly known
+pub const EXAMPLE_SECRET: &str = "change me";
+
+/// Signs image urls for the `/img` route, so that only urls
+/// emitted by this instance are proxied
+#[derive(Debug, Clone)]
+pub struct ImageProxy {
+    secret: String,
+    /// Url the webserver is publicly reachable at
+    public_url: Url,
+}
+
+impl ImageProxy {
+    pub fn new(secret: String, public_url: Url) -> ImageProxy {
+        ImageProxy { secret, public_url }
+    }
+
+    fn mac(&self, url: &str) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(self.secret.as_bytes())
+            .expect("HMAC accepts keys of any size");
+        mac.update(url.as_bytes());
+        mac
+    }
+
+    /// Signature of `url`
+    pub fn sign(&self, url: &str) -> String {
+        URL_SAFE_NO_PAD.encode(self.mac(url).finalize().into_bytes())
+    }
+
+    /// Whether `signature` is a valid signature of `url`, compared in constant time
+    pub fn verify(&self, url: &str, signature: &str) -> bool {
+        match URL_SAFE_NO_PAD.decode(signature) {
+            Ok(signature) => self.mac(url).verify_slice(&signature).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    /// The proxy url serving the image at `url`
+    pub fn proxy_url(&self, url: &str) -> Url {
+        let mut proxy_url = self.public_url.join(IMAGE_PROXY_PATH.trim_start_matches('/'))
+            .unwrap_or_else(|_| self.public_url.clone());
+        proxy_url.query_pairs_mut()
+            .append_pair("url", url)
+            .append_pair("sig", &self.sign(url));
+        proxy_url
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proxy(secret: &str) -> ImageProxy {
+        ImageProxy::new(secret.to_owned(), Url::parse("https://proxy.example.org/ftr/").unwrap())
+    }
+
+    /// The `url` and `sig` parameters of a proxy url
+    fn params(proxy_url: &Url) -> (String, String) {
+        let param = |name| proxy_url.query_pairs().find(|(key, _)| key == name).unwrap().1.into_owned();
+        (param("url"), param("sig"))
+    }
+
+    #[test]
+    fn verifies_its_proxy_urls() {
+        let proxy = proxy("s3cret");
+        let proxy_url = proxy.proxy_url("https://example.org/a.jpg?w=1&h=2");
+        assert_eq!(proxy_url.path(), "/ftr/img");
+        let (url, sig) = params(&proxy_url);
+        assert_eq!(url, "https://example.org/a.jpg?w=1&h=2");
+        assert!(proxy.verify(&url, &sig));
+    }
+
+    #[test]
+    fn rejects_changed_urls_and_signatures() {
+        let proxy = proxy("s3cret");
+        let sig = proxy.sign("https://example.org/a.jpg");
+        assert!(!proxy.verify("https://example.org/b.jpg", &sig));
+        let mut changed = sig.clone().into_bytes();
+        changed[0] = if changed[0] == b'A' { b'B' } else { b'A' };
+        assert!(!proxy.verify("https://example.org/a.jpg", &String::from_utf8(changed).unwrap()));
+        assert!(!proxy.verify("https://example.org/a.jpg", &sig[..sig.len() - 1]));
+        assert!(!proxy.verify("https://example.org/a.jpg", ""));
+    }
+
+    #[test]
+    fn rejects_invalid_base64() {
+        let proxy = proxy("s3cret");
+        let sig = proxy.sign("https://example.org/a.jpg");
+        assert!(!proxy.verify("https://example.org/a.jpg", &format!("{}!", &sig[1..])));
+        assert!(!proxy.verify("https://example.org/a.jpg", "not base64"));
+    }
+
+    #[test]
+    fn rejects_signatures_of_other_secrets() {
+        let sig = proxy("other").sign("https://example.org/a.jpg");
+        assert!(!proxy("s3cret").verify("https://example.org/a.jpg", &sig));
+    }
+}
@@ -0,0 +1,249 @@
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime};
+use scraper::{ElementRef, Html, Selector};
+use serde_json::Value;
+
+use log::*;
+
+/// Article content and metadata embedded as structured data (JSON-LD, microdata)
+#[derive(Debug, Default, Clone)]
+pub struct StructuredArticle {
+    pub headline: Option<String>,
+    /// Article body, either html or plain text
+    pub body: Option<String>,
+    pub author: Option<String>,
+    pub image: Option<String>,
+    pub published: Option<DateTime<FixedOffset>>,
+    pub modified: Option<DateTime<FixedOffset>>,
+}
+
+/// Find the article described by the structured data of `document`
+///
+/// JSON-LD is preferred over microdata, which in turn is preferred over
+/// the state blobs of javascript frameworks (e.g. Next.js' `__NEXT_DATA__`).
+pub fn find_article(document: &Html) -> Option<StructuredArticle> {
+    from_json_ld(document)
+        .or_else(|| from_microdata(document))
+        .or_else(|| from_next_data(document))
+}
+
+/// Whether `types` (a JSON-LD `@type` or a microdata `itemtype`) denotes an article
+fn is_article_type(types: &str) -> bool {
+    types.split_ascii_whitespace()
+        .map(|t| t.rsplit('/').next().unwrap_or(t))
+        .any(|t| t.ends_with("Article") || t.ends_with("BlogPosting") || t == "SocialMediaPosting")
+}
+
+/// Parse a date as found in metadata: RFC 3339, RFC 2822 or ISO 8601 without timezone (taken as UTC)
+pub fn parse_date(date: &str) -> Option<DateTime<FixedOffset>> {
+    let date = date.trim();
+    DateTime::parse_from_rfc3339(date)
+        .or_else(|_| DateTime::parse_from_rfc2822(date))
+        .ok()
+        .or_else(|| {
+            NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S%.f").ok()
+                .or_else(|| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok().and_then(|day| day.and_hms_opt(0, 0, 0)))
+                .map(|naive| naive.and_utc().fixed_offset())
+        })
+}
+
+fn json_ld_objects(document: &Html) -> Vec<Value> {
+    let selector = Selector::parse(r#"script[type="application/ld+json"]"#).unwrap();
+    document.select(&selector)
+        .filter_map(|script| {
+            let json = script.text().collect::<String>();
+            serde_json::from_str::<Value>(&json)
+                .inspect_err(|e| debug!("Invalid JSON-LD: {:?}", e))
+                .ok()
+        })
+        .collect()
+}
+
+/// Search a JSON-LD value, including arrays and `@graph`s, for an article object
+fn find_json_ld_article(value: &Value) -> Option<&serde_json::Map<String, Value>> {
+    match value {
+        Value::Array(values) => values.iter().find_map(find_json_ld_article),
+        Value::Object(object) => {
+            let is_article = match object.get("@type") {
+                Some(Value::String(t)) => is_article_type(t),
+                Some(Value::Array(types)) => types.iter().filter_map(|t| t.as_str()).any(is_article_type),
+                _ => false,
+            };
+            if is_article {
+                Some(object)
+            } else {
+                object.get("@graph").and_then(find_json_ld_article)
+            }
+        },
+        _ => None,
+    }
+}
+
+/// Name(s) of a JSON-LD person/organization, given as string, object or array thereof
+fn json_ld_name(value: &Value) -> Option<String> {
+    match value {
+        Value::String(name) => Some(name.clone()),
+        Value::Object(object) => object.get("name").and_then(json_ld_name),
+        Value::Array(values) => {
+            let names = values.iter().filter_map(json_ld_name).collect::<Vec<_>>();
+            (!names.is_empty()).then(|| names.join(", "))
+        },
+        _ => None,
+    }
+}
+
+/// Url of a JSON-LD image, given as string, `ImageObject` or array thereof
+fn json_ld_url(value: &Value) -> Option<String> {
+    match value {
+        Value::String(url) => Some(url.clone()),
+        Value::Object(object) => object.get("url").or(object.get("contentUrl")).and_then(json_ld_url),
+        Value::Array(values) => values.iter().find_map(json_ld_url),
+        _ => None,
+    }
+}
+
+fn json_ld_string(object: &serde_json::Map<String, Value>, key: &str) -> Option<String> {
+    object.get(key)
+        .and_then(|value| value.as_str())
+        .map(|value| value.trim().to_owned())
+        .filter(|value| !value.is_empty())
+}
+
+fn article_from_json(object: &serde_json::Map<String, Value>) -> StructuredArticle {
+    StructuredArticle {
+        headline: json_ld_string(object, "headline").or_else(|| json_ld_string(object, "name")),
+        body: json_ld_string(object, "articleBody").or_else(|| json_ld_string(object, "text")),
+        author: object.get("author").and_then(json_ld_name),
+        image: object.get("image").and_then(json_ld_url),
+        published: json_ld_string(object, "datePublished").as_deref().and_then(parse_date),
+        modified: json_ld_string(object, "dateModified").as_deref().and_then(parse_date),
+    }
+}
+
+fn from_json_ld(document: &Html) -> Option<StructuredArticle> {
+    json_ld_objects(document).iter()
+        .find_map(find_json_ld_article)
+        .map(article_from_json)
+}
+
+/// Depth-first search for an object carrying an `articleBody`
+fn find_object_with_body(value: &Value) -> Option<&serde_json::Map<String, Value>> {
+    match value {
+        Value::Object(object) if object.get("articleBody").is_some_and(|body| body.is_string()) => Some(object),
+        Value::Object(object) => object.values().find_map(find_object_with_body),
+        Value::Array(values) => values.iter().find_map(find_object_with_body),
+        _ => None,
+    }
+}
+
+fn from_next_data(document: &Html) -> Option<StructuredArticle> {
+    let selector = Selector::parse("script#__NEXT_DATA__, script#__NUXT_DATA__").unwrap();
+    document.select(&selector)
+        .filter_map(|script| serde_json::from_str::<Value>(&script.text().collect::<String>()).ok())
+        .find_map(|value| find_object_with_body(&value).map(article_from_json))
+}
+
+/// The microdata item `element` belongs to
+fn owning_scope<'a>(element: &ElementRef<'a>) -> Option<ElementRef<'a>> {
+    element.ancestors()
+        .filter_map(ElementRef::wrap)
+        .find(|ancestor| ancestor.value().attr("itemscope").is_some())
+}
+
+/// The properties named `name` of the microdata item `scope`
+fn microdata_props<'a>(scope: &ElementRef<'a>, name: &str) -> Vec<ElementRef<'a>> {
+    let selector = Selector::parse("[itemprop]").unwrap();
+    scope.select(&selector)
+        .filter(|prop| {
+            prop.value().attr("itemprop")
+                .is_some_and(|props| props.split_ascii_whitespace().any(|p| p == name))
+        })
+        .filter(|prop| owning_scope(prop).is_some_and(|owner| owner.id() == scope.id()))
+        .collect()
+}
+
+/// The value of a microdata property, following the rules for the element type
+fn microdata_value(prop: &ElementRef) -> Option<String> {
+    let el = prop.value();
+    let value = match el.name() {
+        "meta" => el.attr("content").map(|v| v.to_owned()),
+        "img" | "audio" | "video" | "source" | "iframe" | "embed" => el.attr("src").map(|v| v.to_owned()),
+        "a" | "area" | "link" => el.attr("href").map(|v| v.to_owned()),
+        "time" => el.attr("datetime").map(|v| v.to_owned())
+            .or_else(|| Some(prop.text().collect())),
+        "data" | "meter" => el.attr("value").map(|v| v.to_owned()),
+        _ => Some(prop.text().flat_map(|chunk| chunk.split_whitespace()).collect::<Vec<_>>().join(" ")),
+    };
+    value.map(|v| v.trim().to_owned()).filter(|v| !v.is_empty())
+}
+
+fn microdata_string(scope: &ElementRef, name: &str) -> Option<String> {
+    microdata_props(scope, name).iter().find_map(microdata_value)
+}
+
+fn from_microdata(document: &Html) -> Option<StructuredArticle> {
+    let selector = Selector::parse("[itemscope][itemtype]").unwrap();
+    let scope = document.select(&selector)
+        .find(|scope| scope.value().attr("itemtype").is_some_and(is_article_type))?;
+
+    let author = microdata_props(&scope, "author").iter()
+        .filter_map(|author| {
+            if author.value().attr("itemscope").is_some() {
+                microdata_string(author, "name")
+            } else {
+                microdata_value(author)
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let image = microdata_props(&scope, "image").iter()
+        .find_map(|image| {
+            if image.value().attr("itemscope").is_some() {
+                microdata_string(image, "url")
+            } else {
+                microdata_value(image)
+            }
+        });
+
+    Some(StructuredArticle {
+        headline: microdata_string(&scope, "headline").or_else(|| microdata_string(&scope, "name")),
+        // The body is kept as html, it is the actual rendered article
+        body: microdata_props(&scope, "articleBody").first()
+            .map(|body| body.inner_html())
+            .filter(|body| !body.trim().is_empty()),
+        author: (!author.is_empty()).then(|| author.join(", ")),
+        image,
+        published: microdata_string(&scope, "datePublished").as_deref().and_then(parse_date),
+        modified: microdata_string(&scope, "dateModified").as_deref().and_then(parse_date),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_rfc3339_dates() {
+        let date = parse_date("2024-03-05T10:20:30+01:00").unwrap();
+        assert_eq!(date.to_rfc3339(), "2024-03-05T10:20:30+01:00");
+    }
+
+    #[test]
+    fn parses_rfc2822_dates() {
+        let date = parse_date("Tue, 05 Mar 2024 10:20:30 GMT").unwrap();
+        assert_eq!(date.to_rfc3339(), "2024-03-05T10:20:30+00:00");
+    }
+
+    #[test]
+    fn takes_dates_without_timezone_as_utc() {
+        assert_eq!(parse_date("2024-03-05T10:20:30").unwrap().to_rfc3339(), "2024-03-05T10:20:30+00:00");
+        assert_eq!(parse_date("2024-03-05T10:20:30.250").unwrap().timestamp_millis() % 1000, 250);
+        assert_eq!(parse_date(" 2024-03-05 ").unwrap().to_rfc3339(), "2024-03-05T00:00:00+00:00");
+    }
+
+    #[test]
+    fn rejects_other_formats() {
+        assert!(parse_date("").is_none());
+        assert!(parse_date("yesterday").is_none());
+        assert!(parse_date("05.03.2024").is_none());
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+
+use super::config::SummaryMode;
+use super::html;
+
+/// Sentences with fewer words are not considered for summaries (e.g. captions, bylines)
+const MIN_SENTENCE_WORDS: usize = 5;
+
+/// Abbreviations (lowercase, without their final period) commonly followed by a capitalized word
+const ABBREVIATIONS: &[&str] = &[
+    "mr", "mrs", "ms", "dr", "prof", "st", "jr", "sr", "vs", "no", "nr", "fig", "inc", "ltd", "corp",
+    "gen", "gov", "sen", "rep", "hr", "fr", "vgl", "bzw", "ca", "mio", "mrd",
+];
+
+/// Words too common to tell sentences apart
+const STOPWORDS: &[&str] = &[
+    "the", "and", "that", "this", "with", "for", "from", "was", "were", "are", "have", "has", "had",
+    "not", "but", "they", "their", "there", "which", "what", "when", "will", "would", "could", "should",
+    "been", "also", "into", "than", "then", "them", "more", "about", "other", "some", "such", "only",
+    "der", "die", "das", "und", "ist", "nicht", "mit", "von", "den", "dem", "auch", "sich", "ein", "eine",
+    "les", "des", "une", "est", "pas", "pour", "dans", "que", "qui", "sur", "avec",
+];
+
+/// Summarize the article `html` in at most `sentences` sentences of plain text
+pub fn summarize(html: &str, mode: SummaryMode, sentences: usize) -> Option<String> {
+    if sentences == 0 {
+        return None;
+    }
+    let select: fn(Vec<&str>, usize) -> Vec<&str> = match mode {
+        SummaryMode::Off => return None,
+        SummaryMode::Lead => |candidates, count| candidates.into_iter().take(count).collect(),
+        SummaryMode::Ranked => rank,
+    };
+    let mut paragraphs = html::paragraph_texts(html);
+    if paragraphs.is_empty() {
+        paragraphs.push(html::text_content(html));
+    }
+    let candidates = paragraphs.iter()
+        .flat_map(|paragraph| split_sentences(paragraph))
+        .filter(|sentence| sentence.split_whitespace().count() >= MIN_SENTENCE_WORDS)
+        .collect::<Vec<_>>();
+
+    let selected = select(candidates, sentences);
+    (!selected.is_empty()).then(|| selected.join(" "))
+}
+
+/// Whether the period ending `word` marks an abbreviation or initial rather than the end of a sentence
+fn is_abbreviation(word: &str) -> bool {
+    let word = word.trim_start_matches(|c: char| !c.is_alphanumeric()).to_lowercase();
+    // Initials and the last letter of dotted abbreviations, e.g. "J." or "U.S."
+    let last_part = word.rsplit('.').next().unwrap_or_default();
+    last_part.chars().count() == 1 && last_part.chars().all(char::is_alphabetic)
+        || ABBREVIATIONS.contains(&word.as_str())
+}
+
+/// Split `text` into sentences, ending at `.`, `!` or `?` followed by whitespace
+///
+/// Periods of abbreviations and those followed by a lowercase word do not end sentences.
+fn split_sentences(text: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let at_boundary = chars.peek().is_none_or(|(_, next)| next.is_whitespace());
+        let ends_sentence = at_boundary && match c {
+            '!' | '?' => true,
+            '.' => {
+                let word = text[start..i].rsplit(char::is_whitespace).next().unwrap_or_default();
+                let next_word = text[i + 1..].trim_start();
+                !is_abbreviation(word) && !next_word.starts_with(char::is_lowercase)
+            },
+            _ => false,
+        };
+        if ends_sentence {
+            let end = i + c.len_utf8();
+            sentences.push(text[start..end].trim());
+            start = end;
+        }
+    }
+    sentences.push(text[start..].trim());
+    sentences.retain(|sentence| !sentence.is_empty());
+    sentences
+}
+
+/// Lowercase content words of `sentence`
+fn words(sentence: &str) -> impl Iterator<Item = String> + '_ {
+    sentence.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() > 2)
+        .map(|word| word.to_lowercase())
+        .filter(|word| !STOPWORDS.contains(&word.as_str()))
+}
+
+/// Pick the `count` sentences whose words are most frequent across the text, keeping their order
+fn rank(sentences: Vec<&str>, count: usize) -> Vec<&str> {
+    let mut frequencies = HashMap::<String, usize>::new();
+    for word in sentences.iter().flat_map(|sentence| words(sentence)) {
+        *frequencies.entry(word).or_default() += 1;
+    }
+
+    let mut scored = sentences.iter().enumerate().map(|(position, sentence)| {
+        let words = words(sentence).collect::<Vec<_>>();
+        let score = words.iter().map(|word| frequencies[word] as f64).sum::<f64>() / words.len().max(1) as f64;
+        // Favour the lead, which tends to introduce the topic
+        let score = score * (1.0 + 1.0 / (position as f64 + 1.0));
+        (position, score)
+    }).collect::<Vec<_>>();
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scored.truncate(count);
+    scored.sort_by_key(|(position, _)| *position);
+    scored.into_iter().map(|(position, _)| sentences[position]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_sentences() {
+        assert_eq!(split_sentences("One is here. Two? Three!  Four"), vec!["One is here.", "Two?", "Three!", "Four"]);
+        assert_eq!(split_sentences("Version 1.5 is out. Get it"), vec!["Version 1.5 is out.", "Get it"]);
+        assert_eq!(split_sentences(""), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn keeps_abbreviations_within_sentences() {
+        assert_eq!(split_sentences("Dr. Smith met Prof. Jones. Then he left."), vec!["Dr. Smith met Prof. Jones.", "Then he left."]);
+        assert_eq!(split_sentences("Written by J. R. R. Tolkien. It is long."), vec!["Written by J. R. R. Tolkien.", "It is long."]);
+        assert_eq!(split_sentences("U.S. officials said so. Others did not."), vec!["U.S. officials said so.", "Others did not."]);
+        assert_eq!(split_sentences("Fruit, e.g. apples, is healthy. So is water."), vec!["Fruit, e.g. apples, is healthy.", "So is water."]);
+    }
+
+    #[test]
+    fn skips_short_texts() {
+        assert_eq!(summarize("<p>Too short. Really.</p>", SummaryMode::Lead, 3), None);
+        assert_eq!(summarize("", SummaryMode::Ranked, 3), None);
+    }
+
+    #[test]
+    fn summarizes_the_lead() {
+        let html = "<p>The first sentence is long enough. The second sentence is long enough.</p><p>The third sentence is long enough.</p>";
+        assert_eq!(
+            summarize(html, SummaryMode::Lead, 2).as_deref(),
+            Some("The first sentence is long enough. The second sentence is long enough."),
+        );
+        assert_eq!(summarize(html, SummaryMode::Lead, 5).map(|summary| split_sentences(&summary).len()), Some(3));
+        assert_eq!(summarize(html, SummaryMode::Off, 2), None);
+        assert_eq!(summarize(html, SummaryMode::Lead, 0), None);
+    }
+
+    #[test]
+    fn ranks_representative_sentences_in_order() {
+        let sentences = vec![
+            "Cooking pasta takes a little patience.",
+            "Weather today is mild across the region.",
+            "Fresh pasta needs salted water and patience.",
+            "Serve the pasta with sauce and patience.",
+        ];
+        assert_eq!(rank(sentences.clone(), 2), vec![sentences[0], sentences[3]]);
+        assert!(!rank(sentences.clone(), 3).contains(&sentences[1]));
+        assert_eq!(rank(sentences.clone(), 10), sentences);
+    }
+}
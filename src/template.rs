@@ -0,0 +1,69 @@
+use minijinja::{context, AutoEscape, Environment, Value};
+use std::path::Path;
+
+use super::config::ExtractionStage;
+use super::feeds::ArticleLength;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+const TEMPLATE_NAME: &str = "item";
+
+/// Template rendering the content of every item
+#[derive(Debug)]
+pub struct ItemTemplate {
+    env: Environment<'static>,
+}
+
+/// The values of a single item exposed to the template
+#[derive(Debug)]
+pub struct TemplateItem<'a> {
+    pub title: Option<&'a str>,
+    pub url: Option<&'a str>,
+    pub author: Option<String>,
+    pub date: Option<String>,
+    /// The item content, i.e. original content and full-text as placed
+    pub content: &'a str,
+    pub original: Option<&'a str>,
+    /// The extracted full-text, `None` if extraction failed
+    pub full_text: Option<&'a str>,
+    /// The stage which produced the full-text
+    pub stage: Option<ExtractionStage>,
+    pub length: Option<ArticleLength>,
+}
+
+impl ItemTemplate {
+    /// Load the template at `path`
+    ///
+    /// Plain values (e.g. the title) are html-escaped, the html ones are inserted as they are.
+    pub fn load(path: &Path) -> Result<ItemTemplate> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read template {:?}: {}", path, e))?;
+        let mut env = Environment::new();
+        env.set_auto_escape_callback(|_| AutoEscape::Html);
+        env.add_template_owned(TEMPLATE_NAME, source)?;
+        Ok(ItemTemplate { env })
+    }
+
+    pub fn render(&self, item: &TemplateItem) -> Result<String> {
+        let html_value = |html: Option<&str>| html.map(|html| Value::from_safe_string(html.to_owned()));
+        let domain = item.url
+            .and_then(|url| url::Url::parse(url).ok())
+            .and_then(|url| url.host_str().map(|host| host.trim_start_matches("www.").to_owned()));
+
+        let ctx = context! {
+            title => item.title,
+            url => item.url,
+            domain => domain,
+            author => item.author.as_deref(),
+            date => item.date.as_deref(),
+            content => html_value(Some(item.content)),
+            original => html_value(item.original),
+            full_text => html_value(item.full_text),
+            word_count => item.length.map(|length| length.words),
+            reading_time => item.length.map(|length| length.minutes),
+            status => if item.full_text.is_some() { "extracted" } else { "failed" },
+            stage => item.stage.map(|stage| stage.name()),
+        };
+        Ok(self.env.get_template(TEMPLATE_NAME)?.render(ctx)?)
+    }
+}
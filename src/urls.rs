@@ -0,0 +1,241 @@
+use reqwest::Client;
+use url::Url;
+
+use log::*;
+
+/// Upper bound on the number of wrappers/redirect hops to peel off a single url
+const MAX_RESOLVE_HOPS: usize = 5;
+
+/// Wrapper services, identified by host, and the query parameter holding the target url
+const KNOWN_WRAPPERS: &[(&str, &str)] = &[
+    ("www.google.com", "url"),
+    ("www.google.com", "q"),
+    ("l.facebook.com", "u"),
+    ("lm.facebook.com", "u"),
+    ("l.instagram.com", "u"),
+    ("out.reddit.com", "url"),
+    ("t.umblr.com", "z"),
+    ("href.li", ""),
+    ("www.youtube.com", "q"),
+    ("slack-redir.net", "url"),
+    ("steamcommunity.com", "url"),
+];
+
+fn is_http(url: &Url) -> bool {
+    url.scheme() == "http" || url.scheme() == "https"
+}
+
+/// Extract the target of a known tracking/redirect wrapper from `url` without network access
+///
+/// Returns `None` if `url` is no known wrapper. Other redirectors are only resolved by following
+/// their redirects, as a url parameter alone does not tell a wrapper from e.g. a share page.
+pub fn unwrap_wrapper(url: &Url) -> Option<Url> {
+    let host = url.host_str()?;
+
+    for (wrapper_host, param) in KNOWN_WRAPPERS {
+        if host != *wrapper_host {
+            continue;
+        }
+        // href.li and friends simply append the target after a '?'
+        if param.is_empty() {
+            return url.query().and_then(|query| Url::parse(query).ok()).filter(is_http);
+        }
+        let target = url.query_pairs()
+            .find(|(key, _)| key == param)
+            .and_then(|(_, value)| Url::parse(&value).ok())
+            .filter(is_http);
+        if target.is_some() {
+            return target;
+        }
+    }
+    None
+}
+
+/// Resolve `url` to the final article url, unwrapping known wrappers and following redirects
+///
+/// Resolution is best effort: if the remote fails to answer, the url unwrapped so far is returned.
+pub async fn resolve_url(client: &Client, url: &Url) -> Url {
+    let mut current = url.clone();
+
+    for _ in 0..MAX_RESOLVE_HOPS {
+        if let Some(target) = unwrap_wrapper(&current) {
+            trace!("Unwrapped {} to {}", current, target);
+            current = target;
+            continue;
+        }
+
+        // reqwest follows redirects on its own, the response tells us where we ended up
+        let response = match client.head(current.clone()).send().await {
+            Ok(response) if response.status() != reqwest::StatusCode::METHOD_NOT_ALLOWED => response,
+            _ => match client.get(current.clone()).send().await {
+                Ok(response) => response,
+                Err(e) => {
+                    debug!("Failed to resolve {}: {:?}", current, e);
+                    return current;
+                }
+            }
+        };
+
+        if response.url() == &current {
+            break;
+        }
+        trace!("{} redirects to {}", current, response.url());
+        current = response.url().clone();
+
+        // Only go for another round if we got redirected to yet another wrapper
+        if unwrap_wrapper(&current).is_none() {
+            break;
+        }
+    }
+
+    debug!("Resolved {} to {}", url, current);
+    current
+}
+
+/// Query parameters stripped from every url, a trailing `*` matches any suffix
+pub const DEFAULT_TRACKING_PARAMS: &[&str] = &[
+    "utm_*", "fbclid", "gclid", "gclsrc", "dclid", "gbraid", "wbraid", "msclkid", "yclid", "twclid",
+    "igshid", "mc_cid", "mc_eid", "_hsenc", "_hsmi", "mkt_tok", "oly_anon_id", "oly_enc_id",
+    "vero_id", "vero_conv", "__s", "ck_subscriber_id", "rb_clickid", "wt_mc", "wt_zmc",
+    "ref_src", "ref_url", "spm", "scm", "_ga", "_gl", "ncid", "sr_share",
+];
+
+fn matches_param(rule: &str, name: &str) -> bool {
+    match rule.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => name == rule,
+    }
+}
+
+/// Whether the query parameter `name` is considered tracking,
+/// either by default or due to the `additional` rules
+pub fn is_tracking_param(name: &str, additional: &[String]) -> bool {
+    DEFAULT_TRACKING_PARAMS.iter().any(|rule| matches_param(rule, name))
+        || additional.iter().any(|rule| matches_param(rule, name))
+}
+
+/// Remove tracking query parameters from `url`
+pub fn strip_tracking_params(url: &Url, additional: &[String]) -> Url {
+    if !url.query_pairs().any(|(key, _)| is_tracking_param(&key, additional)) {
+        return url.clone();
+    }
+
+    let kept = url.query_pairs()
+        .filter(|(key, _)| !is_tracking_param(key, additional))
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect::<Vec<_>>();
+
+    let mut stripped = url.clone();
+    if kept.is_empty() {
+        stripped.set_query(None);
+    } else {
+        stripped.query_pairs_mut()
+            .clear()
+            .extend_pairs(kept);
+    }
+    stripped
+}
+
+/// Key identifying the page behind `url` regardless of scheme, `www.` prefix,
+/// fragment, trailing slash, query parameter order and tracking parameters
+pub fn normalized_key(url: &Url) -> String {
+    let host = url.host_str().unwrap_or_default().trim_start_matches("www.");
+    let port = url.port().map(|port| format!(":{}", port)).unwrap_or_default();
+    let mut query = url.query_pairs()
+        .filter(|(key, _)| !is_tracking_param(key, &[]))
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>();
+    query.sort();
+    let path = url.path().trim_end_matches('/');
+    if query.is_empty() {
+        format!("{}{}{}", host, port, path)
+    } else {
+        format!("{}{}{}?{}", host, port, path, query.join("&"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strip(url: &str, additional: &[&str]) -> String {
+        let additional = additional.iter().map(|rule| rule.to_string()).collect::<Vec<_>>();
+        strip_tracking_params(&Url::parse(url).unwrap(), &additional).to_string()
+    }
+
+    #[test]
+    fn strips_default_tracking_params() {
+        assert_eq!(strip("https://example.org/a?utm_source=rss&id=3&fbclid=x", &[]), "https://example.org/a?id=3");
+        assert_eq!(strip("https://example.org/a?utm_medium=feed&utm_campaign=c", &[]), "https://example.org/a");
+    }
+
+    #[test]
+    fn keeps_urls_without_tracking_params() {
+        // Untouched urls keep their exact encoding
+        assert_eq!(strip("https://example.org/a?q=a%20b&page=2#top", &[]), "https://example.org/a?q=a%20b&page=2#top");
+    }
+
+    #[test]
+    fn strips_additional_params() {
+        assert_eq!(strip("https://example.org/a?src=feed&id=3", &["src"]), "https://example.org/a?id=3");
+        assert_eq!(strip("https://example.org/a?ref_x=1&reference=2", &["ref_*"]), "https://example.org/a?reference=2");
+    }
+
+    #[test]
+    fn keeps_the_fragment() {
+        assert_eq!(strip("https://example.org/a?utm_source=rss#section", &[]), "https://example.org/a#section");
+    }
+
+    fn unwrap(url: &str) -> Option<String> {
+        unwrap_wrapper(&Url::parse(url).unwrap()).map(|url| url.to_string())
+    }
+
+    #[test]
+    fn unwraps_known_wrappers() {
+        assert_eq!(unwrap("https://www.google.com/url?sa=t&url=https%3A%2F%2Fexample.org%2Fa%3Fid%3D1").as_deref(), Some("https://example.org/a?id=1"));
+        assert_eq!(unwrap("https://l.facebook.com/l.php?u=https%3A%2F%2Fexample.org%2Fa&h=x").as_deref(), Some("https://example.org/a"));
+        assert_eq!(unwrap("https://href.li/?https://example.org/a").as_deref(), Some("https://example.org/a"));
+    }
+
+    #[test]
+    fn rejects_non_http_targets_of_known_wrappers() {
+        assert_eq!(unwrap("https://l.facebook.com/l.php?u=javascript%3Aalert(1)"), None);
+        assert_eq!(unwrap("https://www.google.com/search?q=rust"), None);
+    }
+
+    #[test]
+    fn keeps_urls_carrying_other_urls() {
+        // Share and login pages carry urls, of the same host or another one
+        assert_eq!(unwrap("https://example.org/share?url=https%3A%2F%2Fexample.org%2Fa"), None);
+        assert_eq!(unwrap("https://example.org/login?to=https%3A%2F%2Fother.org%2Fa"), None);
+    }
+
+    #[test]
+    fn keeps_non_wrappers() {
+        assert_eq!(unwrap("https://example.org/a?id=3"), None);
+    }
+
+    fn key(url: &str) -> String {
+        normalized_key(&Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn normalizes_scheme_www_and_trailing_slash() {
+        assert_eq!(key("https://www.example.org/a/"), key("http://example.org/a"));
+        assert_eq!(key("https://example.org/"), key("https://example.org"));
+        assert_eq!(key("https://example.org/a#comments"), "example.org/a");
+    }
+
+    #[test]
+    fn distinguishes_ports_but_not_default_ones() {
+        assert_eq!(key("https://example.org:443/a"), key("https://example.org/a"));
+        assert_eq!(key("http://example.org:80/a"), key("https://example.org/a"));
+        assert_ne!(key("https://example.org:8080/a"), key("https://example.org/a"));
+    }
+
+    #[test]
+    fn normalizes_the_query() {
+        assert_eq!(key("https://example.org/a?b=2&a=1&utm_source=rss"), "example.org/a?a=1&b=2");
+        assert_ne!(key("https://example.org/a?id=1"), key("https://example.org/a?id=2"));
+    }
+}
@@ -1,52 +1,187 @@
 use log::*;
 use axum::{
     Router,
-    extract::{Query, State, Form},
+    extract::{Query, RawQuery, State, Form},
     routing::get,
 
+    body::Body,
     response::{IntoResponse, Response, Html, Redirect},
-    http::{StatusCode, header},
+    http::{StatusCode, header, HeaderMap},
 
     debug_handler
 };
-use serde::Deserialize;
+use futures::StreamExt;
+use reqwest::Client;
+use serde::{Deserialize, Deserializer};
+use serde::de::{DeserializeOwned, IntoDeserializer};
 use article_scraper::ArticleScraper;
 use std::sync::Arc;
 
 use pathetic::Uri;
 
-use super::config::{ServerConf, ExtractionLimits};
+use super::config::{Conf, ExtractionLimits, ExtractionStage, DomainConf, FilterConf, MergeConf, MaxAge, Since, IframePolicy, ImageProxyConf, LinkMode, ContentPlacement, RssTarget, SummaryMode};
+use std::collections::HashMap;
 
 use super::feeds;
+use super::images;
+use super::filters::{self, FieldPatterns, ItemFilter};
+use super::proxy::{ImageProxy, IMAGE_PROXY_PATH, IMAGE_PROXY_TIMEOUT};
+use super::template::ItemTemplate;
 
-type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
 
 #[derive(Clone, Debug)]
 struct AppState {
     fulltext_rss_filters: Arc<super::config::FullTextRSSFilterConf>,
     defaults: super::config::ExtractionOpts,
     limits: ExtractionLimits,
+    domains: Arc<HashMap<String, DomainConf>>,
+    image_proxy: Option<Arc<ImageProxy>>,
+    image_proxy_conf: Arc<ImageProxyConf>,
+    /// Client retrieving proxied images, which does not follow redirects
+    image_client: Client,
+    template: Option<Arc<ItemTemplate>>,
+    /// Compiled item filters, keyed by feed url
+    filters: Arc<HashMap<String, ItemFilter>>,
+    merge: MergeConf,
 }
 
 #[derive(Deserialize, Debug)]
 struct ExtractionQueryOptions {
     url: String,
     max_items: Option<u32>,
+    max_age: Option<MaxAge>,
+    since: Option<Since>,
     keep_failed: Option<bool>,
-    keep_original_content: Option<bool>
+    keep_original_content: Option<bool>,
+    placement: Option<ContentPlacement>,
+    resolve_redirects: Option<bool>,
+    strip_tracking_params: Option<bool>,
+    enrich_metadata: Option<bool>,
+    overwrite_metadata: Option<bool>,
+    lead_image: Option<bool>,
+    xss: Option<bool>,
+    allow_iframes: Option<bool>,
+    proxy_images: Option<bool>,
+    inline_images: Option<bool>,
+    unknown_iframes: Option<IframePolicy>,
+    links: Option<LinkMode>,
+    rss_target: Option<RssTarget>,
+    reading_time: Option<bool>,
+    reading_time_header: Option<bool>,
+    words_per_minute: Option<usize>,
+    summarize: Option<SummaryMode>,
+    summary_sentences: Option<usize>,
+    detect_language: Option<bool>,
+    #[serde(default, deserialize_with = "comma_separated")]
+    lang: Option<Vec<String>>,
+    sort_by_date: Option<bool>,
+    dedup: Option<bool>,
+    include_title: Option<String>,
+    include_link: Option<String>,
+    include_author: Option<String>,
+    include_category: Option<String>,
+    include_text: Option<String>,
+    exclude_title: Option<String>,
+    exclude_link: Option<String>,
+    exclude_author: Option<String>,
+    exclude_category: Option<String>,
+    exclude_text: Option<String>,
+    min_text_length: Option<usize>,
+    min_paragraphs: Option<usize>,
+    #[serde(default, deserialize_with = "comma_separated")]
+    fallbacks: Option<Vec<ExtractionStage>>,
+}
+
+impl ExtractionQueryOptions {
+    /// The item filter given by the `include_*` and `exclude_*` parameters
+    fn filter(&self) -> std::result::Result<ItemFilter, regex::Error> {
+        Ok(ItemFilter {
+            include: FieldPatterns::compile(&FilterConf {
+                title: self.include_title.clone(),
+                link: self.include_link.clone(),
+                author: self.include_author.clone(),
+                category: self.include_category.clone(),
+                text: self.include_text.clone(),
+            })?,
+            exclude: FieldPatterns::compile(&FilterConf {
+                title: self.exclude_title.clone(),
+                link: self.exclude_link.clone(),
+                author: self.exclude_author.clone(),
+                category: self.exclude_category.clone(),
+                text: self.exclude_text.clone(),
+            })?,
+        })
+    }
+}
+
+/// Deserialize a comma separated list of values, e.g. `fallbacks=readability,structured_data`
+fn comma_separated<'de, D, T>(deserializer: D) -> std::result::Result<Option<Vec<T>>, D::Error>
+    where D: Deserializer<'de>,
+          T: DeserializeOwned
+{
+    let Some(list) = Option::<String>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    list.split(',')
+        .map(|item| item.trim())
+        .filter(|item| !item.is_empty())
+        .map(|item| T::deserialize(item.into_deserializer()))
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map(Some)
 }
 
 /// Merge extraction defaults from config with configuration from the current request, safely
 /// bounding by limits (again from configuration)
-fn determine_effective_extraction_parameters(conf_params: &super::config::ExtractionOpts, req_params: &ExtractionQueryOptions, limits: &ExtractionLimits) -> feeds::ExtractionOpts {
+fn determine_effective_extraction_parameters(conf_params: &super::config::ExtractionOpts, req_params: &ExtractionQueryOptions, limits: &ExtractionLimits, domains: &Arc<HashMap<String, DomainConf>>, image_proxy: &Option<Arc<ImageProxy>>, template: &Option<Arc<ItemTemplate>>) -> feeds::ExtractionOpts {
     feeds::ExtractionOpts {
         max_items: req_params.max_items
                     // Default to largest usize type if parameter is too large
                     .map(|n| usize::try_from(n).unwrap_or(usize::MAX))
                     .or(conf_params.max_items),
+        max_age: req_params.max_age.or(conf_params.max_age).map(|age| age.0),
+        since: req_params.since.or(conf_params.since).map(|since| since.0),
         keep_failed: req_params.keep_failed.unwrap_or(conf_params.keep_failed),
-        keep_original_content: req_params.keep_original_content.unwrap_or(conf_params.keep_original_content),
-    }.bound_by_limits(&limits)
+        placement: ContentPlacement::effective(req_params.placement, req_params.keep_original_content,
+                                               conf_params.placement, conf_params.keep_original_content),
+        separator: conf_params.separator.clone(),
+        resolve_redirects: req_params.resolve_redirects.unwrap_or(conf_params.resolve_redirects),
+        strip_tracking_params: req_params.strip_tracking_params.unwrap_or(conf_params.strip_tracking_params),
+        enrich_metadata: req_params.enrich_metadata.unwrap_or(conf_params.enrich_metadata),
+        overwrite_metadata: req_params.overwrite_metadata.unwrap_or(conf_params.overwrite_metadata),
+        lead_image: req_params.lead_image.unwrap_or(conf_params.lead_image),
+        xss: req_params.xss.unwrap_or(conf_params.xss),
+        allow_iframes: req_params.allow_iframes.unwrap_or(conf_params.allow_iframes),
+        quality: feeds::QualityThreshold {
+            min_text_length: req_params.min_text_length.unwrap_or(conf_params.min_text_length),
+            min_paragraphs: req_params.min_paragraphs.unwrap_or(conf_params.min_paragraphs),
+        },
+        fallbacks: req_params.fallbacks.clone().unwrap_or_else(|| conf_params.fallbacks.clone()),
+        domains: domains.clone(),
+        image_proxy: image_proxy.clone()
+            .filter(|_| req_params.proxy_images.unwrap_or(conf_params.proxy_images)),
+        inline_images: req_params.inline_images.unwrap_or(conf_params.inline_images),
+        unknown_iframes: req_params.unknown_iframes.unwrap_or(conf_params.unknown_iframes),
+        links: req_params.links.unwrap_or(conf_params.links),
+        template: template.clone(),
+        rss_target: req_params.rss_target.unwrap_or(conf_params.rss_target),
+        reading_time: req_params.reading_time.unwrap_or(conf_params.reading_time),
+        reading_time_header: req_params.reading_time_header.unwrap_or(conf_params.reading_time_header),
+        words_per_minute: req_params.words_per_minute.unwrap_or(conf_params.words_per_minute),
+        summarize: req_params.summarize.unwrap_or(conf_params.summarize),
+        summary_sentences: req_params.summary_sentences.unwrap_or(conf_params.summary_sentences),
+        detect_language: req_params.detect_language.unwrap_or(conf_params.detect_language),
+        languages: req_params.lang.clone().unwrap_or_else(|| conf_params.lang.clone()),
+        filter: Default::default(),
+        source_filters: Default::default(),
+        sort_by_date: req_params.sort_by_date.unwrap_or(conf_params.sort_by_date),
+        dedup: req_params.dedup.unwrap_or(conf_params.dedup),
+        inline_limits: images::InlineImageLimits {
+            max_size: conf_params.inline_image_max_size,
+            max_total: conf_params.inline_images_max_total,
+            max_width: conf_params.inline_image_max_width,
+        },
+    }.bound_by_limits(limits)
 }
 
 #[debug_handler]
@@ -54,11 +189,17 @@ async fn makefulltextfeed(Query(extraction_params): Query<ExtractionQueryOptions
     trace!("makefulltextfeed: extraction_params: {:?} state: {:?}", extraction_params, state);
     let scraper = ArticleScraper::new(state.fulltext_rss_filters.get_custom_filterpath().as_deref()).await;
 
-    let extract_conf = determine_effective_extraction_parameters(&state.defaults, &extraction_params, &state.limits);
+    // Filters given as parameters take precedence over the configured ones, field by field
+    let filter = match extraction_params.filter() {
+        Ok(filter) => filter.or(state.filters.get(&extraction_params.url)),
+        Err(e) => return (StatusCode::BAD_REQUEST, format!("Invalid filter: {}", e)).into_response(),
+    };
+    let mut extract_conf = determine_effective_extraction_parameters(&state.defaults, &extraction_params, &state.limits, &state.domains, &state.image_proxy, &state.template);
+    extract_conf.filter = filter;
     trace!("Effective extraction opts: {:?}", extract_conf);
 
     let feed_res = feeds::get_fulltext_feed(&scraper, &extraction_params.url, &extract_conf).await;
-    let response = match feed_res {
+    match feed_res {
         Ok(feed) => {
             (StatusCode::OK, [(header::CONTENT_TYPE, [feed.mime_type(), "charset=UTF-8"].join("; "))], feed.to_string()).into_response()
         }
@@ -66,8 +207,137 @@ async fn makefulltextfeed(Query(extraction_params): Query<ExtractionQueryOptions
             info!("Failed to extract feed {}: {:?}", extraction_params.url, e);
             (StatusCode::BAD_REQUEST, format!("{:?}", e)).into_response()
         }
+    }
+}
+
+/// Options of `/mergefeeds` besides the extraction options
+#[derive(Deserialize, Debug)]
+struct MergeQueryOptions {
+    title: Option<String>,
+    tag_source: Option<bool>,
+}
+
+/// Merge the feeds given by repeated `url` parameters into a single full-text feed
+///
+/// Filters given as parameters take precedence over those configured per feed, field by field.
+async fn mergefeeds(RawQuery(query): RawQuery, State(state): State<Arc<AppState>>) -> Response {
+    let query = query.unwrap_or_default();
+    let pairs = url::form_urlencoded::parse(query.as_bytes()).collect::<Vec<_>>();
+    let urls = pairs.iter()
+        .filter(|(key, _)| key == "url")
+        .map(|(_, value)| value.to_string())
+        .collect::<Vec<_>>();
+    if urls.is_empty() {
+        return (StatusCode::BAD_REQUEST, "at least one url is required").into_response();
+    }
+    if urls.len() > state.limits.max_feeds {
+        return (StatusCode::BAD_REQUEST, format!("at most {} feeds can be merged", state.limits.max_feeds)).into_response();
+    }
+
+    // The remaining options are those of makefulltextfeed, which takes a single url
+    let options_query = url::form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(pairs.iter().filter(|(key, _)| key != "url"))
+        .append_pair("url", &urls[0])
+        .finish();
+    let Ok(options_uri) = format!("/?{}", options_query).parse::<axum::http::Uri>() else {
+        return (StatusCode::BAD_REQUEST, "invalid query").into_response();
+    };
+    let (extraction_params, merge_params) = match (Query::<ExtractionQueryOptions>::try_from_uri(&options_uri), Query::<MergeQueryOptions>::try_from_uri(&options_uri)) {
+        (Ok(Query(extraction_params)), Ok(Query(merge_params))) => (extraction_params, merge_params),
+        (Err(e), _) | (_, Err(e)) => return e.into_response(),
+    };
+    trace!("mergefeeds: urls: {:?} extraction_params: {:?} merge_params: {:?}", urls, extraction_params, merge_params);
+
+    let filter = match extraction_params.filter() {
+        Ok(filter) => filter,
+        Err(e) => return (StatusCode::BAD_REQUEST, format!("Invalid filter: {}", e)).into_response(),
     };
-    return response;
+    let mut extract_conf = determine_effective_extraction_parameters(&state.defaults, &extraction_params, &state.limits, &state.domains, &state.image_proxy, &state.template);
+    extract_conf.filter = filter;
+    let merge_conf = MergeConf {
+        title: merge_params.title.or_else(|| state.merge.title.clone()),
+        tag_source: merge_params.tag_source.unwrap_or(state.merge.tag_source),
+    };
+
+    let scraper = ArticleScraper::new(state.fulltext_rss_filters.get_custom_filterpath().as_deref()).await;
+    match feeds::get_merged_fulltext_feed(&scraper, &urls, &merge_conf, &state.filters, &extract_conf).await {
+        Ok(feed) => {
+            (StatusCode::OK, [(header::CONTENT_TYPE, [feed.mime_type(), "charset=UTF-8"].join("; "))], feed.to_string()).into_response()
+        }
+        Err(e) => {
+            info!("Failed to merge feeds {:?}: {:?}", urls, e);
+            (StatusCode::BAD_REQUEST, format!("{:?}", e)).into_response()
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct ImageProxyQuery {
+    url: String,
+    sig: String,
+}
+
+async fn proxy_image(Query(query): Query<ImageProxyQuery>, State(state): State<Arc<AppState>>) -> Response {
+    let Some(proxy) = &state.image_proxy else {
+        return (StatusCode::NOT_FOUND, "image proxy is not configured").into_response();
+    };
+    if !proxy.verify(&query.url, &query.sig) {
+        return (StatusCode::FORBIDDEN, "invalid signature").into_response();
+    }
+    match fetch_image(&state.image_client, &state.image_proxy_conf, &query.url).await {
+        Ok(response) => response,
+        Err(e) => {
+            info!("Failed to proxy image {}: {:?}", query.url, e);
+            (StatusCode::BAD_GATEWAY, format!("{:?}", e)).into_response()
+        }
+    }
+}
+
+/// Fetch the image at `url` and stream it to the client, enforcing the limits of `conf`
+///
+/// Redirects are not followed, as their targets are not covered by the signature.
+async fn fetch_image(client: &Client, conf: &ImageProxyConf, url: &str) -> Result<Response> {
+    let response = client.get(url).send()
+        .await?
+        .error_for_status()?;
+    if response.status().is_redirection() {
+        return Ok((StatusCode::BAD_GATEWAY, "refusing to follow the redirect of the image").into_response());
+    }
+
+    let content_type = response.headers().get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("")
+        .to_owned();
+    if !conf.accepts_content_type(&content_type) {
+        return Ok((StatusCode::UNSUPPORTED_MEDIA_TYPE, format!("refusing to proxy content type {:?}", content_type)).into_response());
+    }
+    if response.content_length().is_some_and(|length| length > conf.max_size) {
+        return Ok((StatusCode::PAYLOAD_TOO_LARGE, "image exceeds the size limit").into_response());
+    }
+
+    let mut headers = HeaderMap::new();
+    for name in [header::CONTENT_TYPE, header::CACHE_CONTROL, header::LAST_MODIFIED, header::ETAG] {
+        if let Some(value) = response.headers().get(&name) {
+            headers.insert(name, value.clone());
+        }
+    }
+    // Only a length within the limit is known to be sent in full
+    if let Some(length) = response.content_length() {
+        headers.insert(header::CONTENT_LENGTH, length.into());
+    }
+
+    // The announced length may be missing or wrong, so enforce the limit while streaming as well
+    let max_size = conf.max_size;
+    let mut received: u64 = 0;
+    let body = response.bytes_stream().map(move |chunk| -> Result<bytes::Bytes> {
+        let chunk = chunk?;
+        received += chunk.len() as u64;
+        if received > max_size {
+            return Err("image exceeds the size limit".into());
+        }
+        Ok(chunk)
+    });
+    Ok((StatusCode::OK, headers, Body::from_stream(body)).into_response())
 }
 
 async fn show_form() -> Html<&'static str> {
@@ -127,6 +397,102 @@ async fn show_form() -> Html<&'static str> {
                                 <label for="keep_original_false">discard</label>
                             </group>
                         </div>
+
+                        <div>
+                            <span>Resolve redirects and tracking links:</span>
+                            <group>
+                                <input type="radio" id="resolve_redirects_default" name="resolve_redirects" value="Default" checked="checked">
+                                <label for="resolve_redirects_default">use instance default</label>
+                                <input type="radio" id="resolve_redirects_true" name="resolve_redirects" value="True">
+                                <label for="resolve_redirects_true">resolve</label>
+                                <input type="radio" id="resolve_redirects_false" name="resolve_redirects" value="False">
+                                <label for="resolve_redirects_false">keep as is</label>
+                            </group>
+                        </div>
+
+                        <div>
+                            <span>Strip tracking parameters:</span>
+                            <group>
+                                <input type="radio" id="strip_tracking_default" name="strip_tracking_params" value="Default" checked="checked">
+                                <label for="strip_tracking_default">use instance default</label>
+                                <input type="radio" id="strip_tracking_true" name="strip_tracking_params" value="True">
+                                <label for="strip_tracking_true">strip</label>
+                                <input type="radio" id="strip_tracking_false" name="strip_tracking_params" value="False">
+                                <label for="strip_tracking_false">keep as is</label>
+                            </group>
+                        </div>
+
+                        <div>
+                            <span>Fill in missing titles, authors and dates:</span>
+                            <group>
+                                <input type="radio" id="enrich_metadata_default" name="enrich_metadata" value="Default" checked="checked">
+                                <label for="enrich_metadata_default">use instance default</label>
+                                <input type="radio" id="enrich_metadata_true" name="enrich_metadata" value="True">
+                                <label for="enrich_metadata_true">fill in</label>
+                                <input type="radio" id="enrich_metadata_false" name="enrich_metadata" value="False">
+                                <label for="enrich_metadata_false">keep as is</label>
+                            </group>
+                        </div>
+
+                        <div>
+                            <span>Attach lead image as enclosure:</span>
+                            <group>
+                                <input type="radio" id="lead_image_default" name="lead_image" value="Default" checked="checked">
+                                <label for="lead_image_default">use instance default</label>
+                                <input type="radio" id="lead_image_true" name="lead_image" value="True">
+                                <label for="lead_image_true">attach</label>
+                                <input type="radio" id="lead_image_false" name="lead_image" value="False">
+                                <label for="lead_image_false">leave out</label>
+                            </group>
+                        </div>
+
+                        <div>
+                            <span>Sanitize content (XSS mitigation):</span>
+                            <group>
+                                <input type="radio" id="xss_default" name="xss" value="Default" checked="checked">
+                                <label for="xss_default">use instance default</label>
+                                <input type="radio" id="xss_true" name="xss" value="True">
+                                <label for="xss_true">sanitize</label>
+                                <input type="radio" id="xss_false" name="xss" value="False">
+                                <label for="xss_false">keep as is</label>
+                            </group>
+                        </div>
+
+                        <div>
+                            <span>Load images via this instance:</span>
+                            <group>
+                                <input type="radio" id="proxy_images_default" name="proxy_images" value="Default" checked="checked">
+                                <label for="proxy_images_default">use instance default</label>
+                                <input type="radio" id="proxy_images_true" name="proxy_images" value="True">
+                                <label for="proxy_images_true">proxy</label>
+                                <input type="radio" id="proxy_images_false" name="proxy_images" value="False">
+                                <label for="proxy_images_false">load directly</label>
+                            </group>
+                        </div>
+
+                        <div>
+                            <span>Embed images for offline reading:</span>
+                            <group>
+                                <input type="radio" id="inline_images_default" name="inline_images" value="Default" checked="checked">
+                                <label for="inline_images_default">use instance default</label>
+                                <input type="radio" id="inline_images_true" name="inline_images" value="True">
+                                <label for="inline_images_true">embed</label>
+                                <input type="radio" id="inline_images_false" name="inline_images" value="False">
+                                <label for="inline_images_false">link</label>
+                            </group>
+                        </div>
+
+                        <div>
+                            <span>Show word count and reading time:</span>
+                            <group>
+                                <input type="radio" id="reading_time_header_default" name="reading_time_header" value="Default" checked="checked">
+                                <label for="reading_time_header_default">use instance default</label>
+                                <input type="radio" id="reading_time_header_true" name="reading_time_header" value="True">
+                                <label for="reading_time_header_true">show</label>
+                                <input type="radio" id="reading_time_header_false" name="reading_time_header" value="False">
+                                <label for="reading_time_header_false">leave out</label>
+                            </group>
+                        </div>
                     </div>
                     <input type="submit" value="Get full-text feed!">
                 </form>
@@ -150,6 +516,31 @@ struct Input {
     max_items: Option<usize>,
     keep_failed: TriState,
     keep_original_content: TriState,
+    resolve_redirects: TriState,
+    strip_tracking_params: TriState,
+    enrich_metadata: TriState,
+    lead_image: TriState,
+    xss: TriState,
+    proxy_images: TriState,
+    inline_images: TriState,
+    reading_time_header: TriState,
+}
+
+impl TriState {
+    /// Append `name` to the query of `uri`, unless the instance default is requested
+    fn append_to(&self, uri: &mut Uri, name: &str) {
+        match self {
+            TriState::True => {
+                uri.query_pairs_mut()
+                    .append_pair(name, "true");
+            },
+            TriState::False => {
+                uri.query_pairs_mut()
+                    .append_pair(name, "false");
+            },
+            TriState::Default => (),
+        };
+    }
 }
 
 async fn accept_form(Form(input): Form<Input>) -> Redirect {
@@ -162,46 +553,57 @@ async fn accept_form(Form(input): Form<Input>) -> Redirect {
         uri.query_pairs_mut()
             .append_pair("max_items", &max_items.to_string());
     }
-    match input.keep_failed {
-        TriState::True => {
-            uri.query_pairs_mut()
-                .append_pair("keep_failed", "true");
-        },
-        TriState::False => {
-            uri.query_pairs_mut()
-                .append_pair("keep_failed", "false");
-        },
-        TriState::Default => (),
-    };
-    match input.keep_original_content {
-        TriState::True => {
-            uri.query_pairs_mut()
-                .append_pair("keep_original_content", "true");
-        },
-        TriState::False => {
-            uri.query_pairs_mut()
-                .append_pair("keep_original_content", "false");
-        },
-        TriState::Default => (),
-    }
+    input.keep_failed.append_to(&mut uri, "keep_failed");
+    input.keep_original_content.append_to(&mut uri, "keep_original_content");
+    input.resolve_redirects.append_to(&mut uri, "resolve_redirects");
+    input.strip_tracking_params.append_to(&mut uri, "strip_tracking_params");
+    input.enrich_metadata.append_to(&mut uri, "enrich_metadata");
+    input.lead_image.append_to(&mut uri, "lead_image");
+    input.xss.append_to(&mut uri, "xss");
+    input.proxy_images.append_to(&mut uri, "proxy_images");
+    input.inline_images.append_to(&mut uri, "inline_images");
+    input.reading_time_header.append_to(&mut uri, "reading_time_header");
     Redirect::to(uri.as_str())
 }
 
-pub async fn serve(listen_conf: ServerConf, fulltextrss_filter_conf: super::config::FullTextRSSFilterConf, extraction_defaults: super::config::ExtractionOpts, extraction_limits: ExtractionLimits) -> Result<()> {
-    // build our application with a single route
+pub async fn serve(conf: Conf) -> Result<()> {
+    let image_proxy = conf.image_proxy.build()?.map(Arc::new);
+    let template = conf.extraction_defaults.template.as_deref()
+        .map(ItemTemplate::load)
+        .transpose()?
+        .map(Arc::new);
+    let filters = filters::compile_feed_filters(&conf.feeds)?;
+    let image_client = Client::builder()
+        .timeout(IMAGE_PROXY_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?;
+    if image_proxy.is_none() && conf.extraction_defaults.proxy_images {
+        warn!("proxy_images requires image_proxy.secret and image_proxy.public_url to be set");
+    }
+
+    // build our application
     let app = Router::new()
         .route("/", get(show_form).post(accept_form))
         .route("/makefulltextfeed", get(makefulltextfeed))
+        .route("/mergefeeds", get(mergefeeds))
+        .route(IMAGE_PROXY_PATH, get(proxy_image))
         .with_state(Arc::new(AppState {
-            fulltext_rss_filters: Arc::new(fulltextrss_filter_conf),
-            defaults: extraction_defaults,
-            limits: extraction_limits
+            fulltext_rss_filters: Arc::new(conf.fulltext_rss_filters),
+            defaults: conf.extraction_defaults,
+            limits: conf.extraction_limits,
+            domains: Arc::new(conf.domains),
+            image_proxy,
+            image_proxy_conf: Arc::new(conf.image_proxy),
+            image_client,
+            template,
+            filters: Arc::new(filters),
+            merge: conf.merge,
         }));
 
     let listener = tokio_listener::Listener::bind(
-        &listen_conf.address,
+        &conf.listen.address,
         &tokio_listener::SystemOptions::default(),
-        &listen_conf.options.unwrap_or_default()
+        &conf.listen.options.unwrap_or_default()
     )
     .await?;
 